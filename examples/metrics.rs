@@ -0,0 +1,97 @@
+use std::{
+    env, thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Result};
+use concurrency::{AmapMetrics, CancellationToken};
+use rand::Rng;
+
+const WORKERS: usize = 8;
+const REPORTERS: usize = 1;
+const RUN_SECS: u64 = 5;
+const ENDPOINTS: [&str; 4] = ["req./", "req./login", "req./search", "req./cart"];
+
+// usage: cargo run --example metrics -- [workers] [reporters] [seconds]
+fn main() -> Result<()> {
+    let mut args = env::args().skip(1);
+    let workers = args.next().map_or(Ok(WORKERS), |v| v.parse())?;
+    let reporters = args.next().map_or(Ok(REPORTERS), |v| v.parse())?;
+    let run_secs = args.next().map_or(Ok(RUN_SECS), |v| v.parse())?;
+
+    let metrics = ENDPOINTS.iter().copied().collect::<AmapMetrics>();
+    let stop = CancellationToken::new();
+
+    let worker_handles = (0..workers)
+        .map(|_| request_worker(metrics.clone(), stop.clone()))
+        .collect::<Vec<_>>();
+    let reporter_handles = (0..reporters)
+        .map(|idx| reporter(idx, metrics.clone(), stop.clone()))
+        .collect::<Vec<_>>();
+
+    thread::sleep(Duration::from_secs(run_secs));
+    stop.cancel();
+
+    let mut handled = 0;
+    for handle in worker_handles {
+        handled += handle
+            .join()
+            .map_err(|e| anyhow!("Thread join error: {:?}", e))??;
+    }
+    for handle in reporter_handles {
+        handle
+            .join()
+            .map_err(|e| anyhow!("Thread join error: {:?}", e))?;
+    }
+
+    // every request a worker handled must show up in the shared counters
+    let counted = metrics
+        .sorted_snapshot()
+        .iter()
+        .map(|(_, v)| v)
+        .sum::<i64>();
+    println!("final: {}", metrics);
+    println!("handled {} requests, counted {}", handled, counted);
+    if handled != counted {
+        return Err(anyhow!("lost {} updates", handled - counted));
+    }
+
+    Ok(())
+}
+
+// simulate handling requests for random endpoints until stopped
+fn request_worker(
+    metrics: AmapMetrics,
+    stop: CancellationToken,
+) -> thread::JoinHandle<Result<i64>> {
+    thread::spawn(move || {
+        let mut rng = rand::thread_rng();
+        let mut handled = 0;
+        while !stop.is_cancelled() {
+            thread::sleep(Duration::from_micros(rng.gen_range(10..1000)));
+            metrics.inc(ENDPOINTS[rng.gen_range(0..ENDPOINTS.len())])?;
+            handled += 1;
+        }
+        Ok(handled)
+    })
+}
+
+fn reporter(idx: usize, metrics: AmapMetrics, stop: CancellationToken) -> thread::JoinHandle<()> {
+    thread::spawn(move || {
+        let start = Instant::now();
+        while !stop.is_cancelled() {
+            thread::sleep(Duration::from_secs(1));
+            let snapshot = metrics
+                .sorted_snapshot()
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect::<Vec<_>>();
+            println!(
+                "[reporter {} @ {:.0?}] {}",
+                idx,
+                start.elapsed(),
+                snapshot.join(" ")
+            );
+        }
+    })
+}
@@ -0,0 +1,93 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+use anyhow::Result;
+use concurrency::{multiply_direct, Matrix, MultiplyConfig};
+use rand::Rng;
+
+const N: usize = 300;
+
+// tracks live and peak heap bytes, and the number of allocations (reallocations
+// included), so the strategies can be compared
+struct CountingAlloc;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+static ALLOCS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = System.alloc(layout);
+        if !ptr.is_null() {
+            ALLOCS.fetch_add(1, Ordering::Relaxed);
+            let current = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(current, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout);
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new = System.realloc(ptr, layout, new_size);
+        if !new.is_null() {
+            ALLOCS.fetch_add(1, Ordering::Relaxed);
+            CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+            let current = CURRENT.fetch_add(new_size, Ordering::Relaxed) + new_size;
+            PEAK.fetch_max(current, Ordering::Relaxed);
+        }
+        new
+    }
+}
+
+#[global_allocator]
+static ALLOC: CountingAlloc = CountingAlloc;
+
+fn measure<F>(name: &str, f: F) -> Result<Matrix<f64>>
+where
+    F: FnOnce() -> Result<Matrix<f64>>,
+{
+    let baseline = CURRENT.load(Ordering::Relaxed);
+    PEAK.store(baseline, Ordering::Relaxed);
+    let allocs = ALLOCS.load(Ordering::Relaxed);
+    let start = Instant::now();
+    let result = f()?;
+    let elapsed = start.elapsed();
+    let peak = PEAK.load(Ordering::Relaxed) - baseline;
+    let allocs = ALLOCS.load(Ordering::Relaxed) - allocs;
+    println!(
+        "{:<16} {:>10.2?} peak heap {:>8} KiB {:>9} allocations",
+        name,
+        elapsed,
+        peak / 1024,
+        allocs
+    );
+    Ok(result)
+}
+
+// cargo run --release --example multiply_bench
+//
+// `multiply` makes ~3 allocations per output cell (its row, its column and the reply
+// channel), none of them reallocations. Copying columns out of a transposed b instead of
+// gathering them with a strided walk took it from ~225ms to ~210ms on a 4-core machine;
+// its peak heap depends on how far dispatch runs ahead of the workers and varies by run.
+fn main() -> Result<()> {
+    let mut rng = rand::thread_rng();
+    let a = Matrix::from_fn(N, N, |_, _| rng.gen::<f64>());
+    let b = Matrix::from_fn(N, N, |_, _| rng.gen::<f64>());
+
+    println!("{}x{} * {}x{}", N, N, N, N);
+    // pin the thread count so runs are comparable across machines
+    let multiplier = MultiplyConfig::new().threads(4).build();
+    let channels = measure("multiply", || multiplier.multiply(&a, &b))?;
+    let direct = measure("multiply_direct", || multiply_direct(&a, &b))?;
+    assert!(channels.approx_eq(&direct, 1e-9));
+
+    Ok(())
+}
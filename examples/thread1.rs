@@ -1,7 +1,13 @@
 use anyhow::{anyhow, Result};
-use std::{sync::mpsc, thread, time::Duration};
+use std::{
+    env,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
 
 const PRODUCE_NUM: usize = 4;
+const CONSUME_NUM: usize = 1;
 
 #[allow(dead_code)]
 #[derive(Debug)]
@@ -16,42 +22,98 @@ impl Msg {
     }
 }
 
-fn produce(idx: usize, tx: mpsc::Sender<Msg>) -> Result<()> {
+fn produce(idx: usize, tx: mpsc::Sender<Msg>) -> Result<usize> {
+    let mut sent = 0;
     loop {
         let data = rand::random::<usize>();
         let msg = Msg::new(idx, data);
-        tx.send(msg).unwrap();
+        tx.send(msg)?;
+        sent += 1;
         let sleep_time = rand::random::<u8>() as u64 * 10;
         thread::sleep(Duration::from_millis(sleep_time));
 
-        if rand::random::<u8>() % 5 == 0 {
+        if rand::random::<u8>().is_multiple_of(5) {
             println!("produce {} exit", idx);
             break;
         }
     }
 
-    Ok(())
+    Ok(sent)
 }
 
-fn main() -> Result<()> {
-    let (tx, rx) = mpsc::channel();
+// consumers share one receiver; the lock is only held while waiting for the next message
+fn consume(idx: usize, rx: Arc<Mutex<mpsc::Receiver<Msg>>>) -> Result<usize> {
+    let mut received = 0;
+    loop {
+        let msg = rx
+            .lock()
+            .map_err(|e| anyhow!("Receiver lock poisoned: {:?}", e))?
+            .recv();
+        match msg {
+            Ok(msg) => {
+                println!("consume {}: {:?}", idx, msg);
+                received += 1;
+            }
+            // all producers are gone and the channel is drained
+            Err(_) => break,
+        }
+    }
+    println!("consumer {} exit", idx);
+
+    Ok(received)
+}
 
-    for i in 0..PRODUCE_NUM {
-        let tx = tx.clone();
-        thread::spawn(move || produce(i, tx));
+fn join_all(handles: Vec<thread::JoinHandle<Result<usize>>>) -> Result<usize> {
+    let mut total = 0;
+    for handle in handles {
+        total += handle
+            .join()
+            .map_err(|e| anyhow!("Thread join error: {:?}", e))??;
     }
+    Ok(total)
+}
+
+fn run_pipeline(producers: usize, consumers: usize) -> Result<()> {
+    let start = Instant::now();
+    let (tx, rx) = mpsc::channel();
+    let rx = Arc::new(Mutex::new(rx));
+
+    let producer_handles = (0..producers)
+        .map(|i| {
+            let tx = tx.clone();
+            thread::spawn(move || produce(i, tx))
+        })
+        .collect::<Vec<_>>();
     drop(tx);
 
-    let consumer = thread::spawn(move || {
-        for msg in rx {
-            println!("consume: {:?}", msg);
-        }
-        println!("consumer exit");
-    });
+    let consumer_handles = (0..consumers)
+        .map(|i| {
+            let rx = Arc::clone(&rx);
+            thread::spawn(move || consume(i, rx))
+        })
+        .collect::<Vec<_>>();
 
-    consumer
-        .join()
-        .map_err(|e| anyhow!("Thread join error: {:?}", e))?;
+    let sent = join_all(producer_handles)?;
+    let received = join_all(consumer_handles)?;
+    let elapsed = start.elapsed();
+    println!(
+        "{} producers, {} consumers: sent {}, received {} in {:?} ({:.1} msg/s)",
+        producers,
+        consumers,
+        sent,
+        received,
+        elapsed,
+        received as f64 / elapsed.as_secs_f64()
+    );
 
     Ok(())
 }
+
+// usage: cargo run --example thread1 -- [producers] [consumers]
+fn main() -> Result<()> {
+    let mut args = env::args().skip(1);
+    let producers = args.next().map_or(Ok(PRODUCE_NUM), |v| v.parse())?;
+    let consumers = args.next().map_or(Ok(CONSUME_NUM), |v| v.parse())?;
+
+    run_pipeline(producers, consumers)
+}
@@ -1,7 +1,25 @@
+mod cancel;
 mod matrix;
 mod metrics;
+mod num;
+mod pool;
+mod spawner;
 mod vector;
 
-pub use matrix::{multiply, Matrix};
-pub use metrics::{AmapMetrics, CmapMetrics};
-pub use vector::{dot_product, Vector};
+pub use cancel::CancellationToken;
+pub use matrix::{
+    multiply, multiply_cancellable, multiply_direct, multiply_in_pool, multiply_mod,
+    multiply_narrowing, multiply_pattern, multiply_streaming, multiply_timed, multiply_with_policy,
+    multiply_with_stats, outer_difference, recommended_threads, trace_of_product, DisplayOptions,
+    Matrix, Multiplier, MultiplyConfig, Overflow, RowView, WorkerStats,
+};
+#[cfg(feature = "rand")]
+pub use matrix::{verify_product, verify_product_approx};
+pub use metrics::*;
+pub use num::{FusedMulAdd, LossyFrom, Numeric, OverflowArith, WrappingAdd};
+pub use pool::ThreadPool;
+pub use spawner::{Spawner, StdSpawner, Task};
+pub use vector::{
+    dot_product, dot_product_checked_nan, dot_product_slice, elementwise_max, elementwise_min,
+    euclidean_distance, Vector,
+};
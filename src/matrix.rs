@@ -1,183 +1,3234 @@
-use crate::{dot_product, Vector};
+use crate::{
+    dot_product, dot_product_slice, euclidean_distance, CancellationToken, FusedMulAdd, LossyFrom,
+    Metrics, Numeric, OverflowArith, Spawner, Vector, WrappingAdd,
+};
 use anyhow::{anyhow, Result};
 use core::fmt;
 use std::{
-    fmt::Debug,
-    ops::{Add, AddAssign, Mul},
-    sync::mpsc,
+    collections::HashMap,
+    hash::Hash,
+    ops::{Add, Deref, Mul, Range, Sub},
+    sync::{mpsc, Arc},
     thread,
+    time::{Duration, Instant},
 };
 
+#[cfg(feature = "num-complex")]
+use num_complex::Complex;
+
+mod mtx;
+
 const NUM_THREADS: usize = 4;
+// multiply-adds a worker should have before another thread pays for itself
+const MIN_WORK_PER_THREAD: usize = 1 << 16;
 
+#[derive(PartialEq)]
 pub struct Matrix<T> {
     rows: usize,
     cols: usize,
     data: Vec<T>,
 }
 
+// a borrowed row of a matrix, read in place instead of being copied into a Vector
+pub struct RowView<'a, T> {
+    data: &'a [T],
+}
+
+impl<T> Deref for RowView<'_, T> {
+    type Target = [T];
+
+    fn deref(&self) -> &Self::Target {
+        self.data
+    }
+}
+
 impl<T: fmt::Debug> Matrix<T> {
+    /// Wrap row-major `data` as a `rows x cols` matrix. Debug builds panic unless it holds
+    /// exactly `rows * cols` elements; use `try_new` for sizes from untrusted input.
+    #[track_caller]
     pub fn new(rows: usize, cols: usize, data: impl Into<Vec<T>>) -> Self {
-        Self {
+        let m = Self {
             rows,
             cols,
             data: data.into(),
+        };
+        if cfg!(debug_assertions) {
+            if let Err(e) = m.check_shape() {
+                panic!("{}", e);
+            }
         }
+        m
     }
-}
 
-pub struct MsgInput<T> {
-    idx: usize,
-    row: Vector<T>,
-    col: Vector<T>,
+    // like `new`, but a length that doesn't match the shape is an error in every build
+    pub fn try_new(rows: usize, cols: usize, data: impl Into<Vec<T>>) -> Result<Self> {
+        let m = Self {
+            rows,
+            cols,
+            data: data.into(),
+        };
+        m.check_shape()?;
+        Ok(m)
+    }
 }
 
-impl<T> MsgInput<T> {
-    pub fn new(idx: usize, row: Vector<T>, col: Vector<T>) -> Self {
-        Self { idx, row, col }
+impl<T> Matrix<T> {
+    // build a matrix by calling f(row, col) for every cell in row-major order
+    pub fn from_fn(rows: usize, cols: usize, mut f: impl FnMut(usize, usize) -> T) -> Self {
+        let mut data = Vec::with_capacity(rows * cols);
+        for i in 0..rows {
+            for j in 0..cols {
+                data.push(f(i, j));
+            }
+        }
+        Self { rows, cols, data }
     }
-}
 
-pub struct MsgOutput<T> {
-    idx: usize,
-    value: T,
+    /// Build a matrix from rows of length `cols` pulled one at a time from `iter`; the row
+    /// count is however many rows the iterator yields. Fails on the first row of the
+    /// wrong length.
+    pub fn try_from_row_iter<I>(cols: usize, iter: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = Vec<T>>,
+    {
+        let mut rows = 0;
+        let mut data = Vec::new();
+        for row in iter {
+            if row.len() != cols {
+                return Err(anyhow!(
+                    "Row {} has length {}, expected {}",
+                    rows,
+                    row.len(),
+                    cols
+                ));
+            }
+            data.extend(row);
+            rows += 1;
+        }
+        Ok(Self { rows, cols, data })
+    }
+
+    /// Stack row blocks (e.g. the per-node results of a `split_rows` distributed multiply)
+    /// back into one matrix. Every block must have `total_cols` columns.
+    pub fn assemble_row_blocks(blocks: Vec<Matrix<T>>, total_cols: usize) -> Result<Self> {
+        let mut rows = 0;
+        let mut data = Vec::new();
+        for (idx, block) in blocks.into_iter().enumerate() {
+            if block.cols != total_cols {
+                return Err(anyhow!(
+                    "Block {} has {} columns, expected {}",
+                    idx,
+                    block.cols,
+                    total_cols
+                ));
+            }
+            block.check_shape()?;
+            rows += block.rows;
+            data.extend(block.data);
+        }
+        Ok(Self {
+            rows,
+            cols: total_cols,
+            data,
+        })
+    }
+
+    pub fn map<U>(&self, f: impl FnMut(&T) -> U) -> Matrix<U> {
+        Matrix {
+            rows: self.rows,
+            cols: self.cols,
+            data: self.data.iter().map(f).collect(),
+        }
+    }
+
+    // like `map` when the type doesn't change, but reuses this matrix's storage
+    pub fn map_in_place<F: FnMut(&mut T)>(&mut self, f: F) {
+        self.data.iter_mut().for_each(f);
+    }
+
+    /// Development aid: panic, pointing at the caller, unless this is a `rows x cols`
+    /// matrix holding `rows * cols` elements. Compiled out of release builds; use
+    /// `try_new` to validate untrusted input.
+    #[track_caller]
+    pub fn expect_shape(&self, rows: usize, cols: usize) -> &Self {
+        debug_assert!(
+            self.rows == rows && self.cols == cols && self.data.len() == rows * cols,
+            "expected a {}x{} matrix, got {}x{} holding {} elements",
+            rows,
+            cols,
+            self.rows,
+            self.cols,
+            self.data.len()
+        );
+        self
+    }
+
+    // `new` doesn't validate, so a hand-built matrix may not hold rows * cols elements
+    fn check_shape(&self) -> Result<()> {
+        if self.rows.checked_mul(self.cols) != Some(self.data.len()) {
+            return Err(anyhow!(
+                "Matrix declared as {}x{} holds {} elements",
+                self.rows,
+                self.cols,
+                self.data.len()
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn row_view(&self, i: usize) -> Result<RowView<'_, T>> {
+        if i >= self.rows {
+            return Err(anyhow!("Row {} out of range for {} rows", i, self.rows));
+        }
+        Ok(RowView {
+            data: &self.data[i * self.cols..(i + 1) * self.cols],
+        })
+    }
+
+    // like `map`, but stops at the first element `f` fails on
+    pub fn try_map<U, E, F>(&self, f: F) -> Result<Matrix<U>, E>
+    where
+        F: FnMut(&T) -> Result<U, E>,
+    {
+        Ok(Matrix {
+            rows: self.rows,
+            cols: self.cols,
+            data: self.data.iter().map(f).collect::<Result<Vec<_>, E>>()?,
+        })
+    }
+
+    /// Iterate the elements by reference in column-major order, without allocating.
+    pub fn iter_colmajor(&self) -> impl Iterator<Item = &T> {
+        (0..self.cols).flat_map(move |j| (0..self.rows).map(move |i| &self.data[i * self.cols + j]))
+    }
 }
 
-pub struct Msg<T> {
-    input: MsgInput<T>,
-    sender: oneshot::Sender<MsgOutput<T>>,
+impl<T: Copy> Matrix<T> {
+    // stack equal-length vectors as the rows of a new matrix
+    pub fn from_rows(rows: &[Vector<T>]) -> Result<Self> {
+        let cols = rows.first().map_or(0, |row| row.len());
+        let mut data = Vec::with_capacity(rows.len() * cols);
+        for (i, row) in rows.iter().enumerate() {
+            if row.len() != cols {
+                return Err(anyhow!(
+                    "Row {} has length {}, expected {}",
+                    i,
+                    row.len(),
+                    cols
+                ));
+            }
+            data.extend_from_slice(row);
+        }
+        Ok(Self {
+            rows: rows.len(),
+            cols,
+            data,
+        })
+    }
+
+    // clear `buf` and fill it with column j, so one buffer can be reused across columns
+    pub fn copy_col_into(&self, j: usize, buf: &mut Vec<T>) -> Result<()> {
+        if j >= self.cols {
+            return Err(anyhow!(
+                "Column {} out of range for {} columns",
+                j,
+                self.cols
+            ));
+        }
+        buf.clear();
+        buf.extend((0..self.rows).map(|i| self.data[i * self.cols + j]));
+        Ok(())
+    }
+
+    pub fn diagonal(&self) -> Vec<T> {
+        self.diagonal_at(0)
+    }
+
+    /// The diagonal `offset` positions above (positive) or below (negative) the main one,
+    /// read from its top-left end. Empty if the offset lies outside the matrix.
+    pub fn diagonal_at(&self, offset: isize) -> Vec<T> {
+        let (row0, col0) = if offset >= 0 {
+            (0, offset.unsigned_abs())
+        } else {
+            (offset.unsigned_abs(), 0)
+        };
+        (0..)
+            .map(|k| (row0 + k, col0 + k))
+            .take_while(|&(i, j)| i < self.rows && j < self.cols)
+            .map(|(i, j)| self.data[i * self.cols + j])
+            .collect()
+    }
+
+    // lossless element conversion, e.g. i32 -> f64
+    pub fn cast<U: From<T>>(&self) -> Matrix<U> {
+        self.map(|&v| U::from(v))
+    }
+
+    // `as` conversion, which may truncate or wrap, e.g. f64 -> i32
+    pub fn cast_lossy<U: LossyFrom<T>>(&self) -> Matrix<U> {
+        self.map(|&v| U::lossy_from(v))
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut data = Vec::with_capacity(self.data.len());
+        for j in 0..self.cols {
+            for i in 0..self.rows {
+                data.push(self.data[i * self.cols + j]);
+            }
+        }
+        Self {
+            rows: self.cols,
+            cols: self.rows,
+            data,
+        }
+    }
+
+    /// Split the matrix into `n` contiguous row blocks of `rows / n` rows each, the last
+    /// block absorbing the remainder. When `n` is larger than `rows`, fewer blocks are
+    /// returned (one row per block) rather than empty ones; `n == 0` is treated as 1.
+    pub fn split_rows(&self, n: usize) -> Vec<Matrix<T>> {
+        let n = n.min(self.rows).max(1);
+        let chunk = self.rows / n;
+        (0..n)
+            .map(|k| {
+                let start = k * chunk;
+                let end = if k == n - 1 { self.rows } else { start + chunk };
+                Matrix {
+                    rows: end - start,
+                    cols: self.cols,
+                    data: self.data[start * self.cols..end * self.cols].to_vec(),
+                }
+            })
+            .collect()
+    }
 }
 
-impl<T> Msg<T> {
-    pub fn new(input: MsgInput<T>, sender: oneshot::Sender<MsgOutput<T>>) -> Self {
-        Self { input, sender }
+#[cfg(feature = "num-complex")]
+impl Matrix<Complex<f64>> {
+    pub fn conjugate(&self) -> Self {
+        Self::new(
+            self.rows,
+            self.cols,
+            self.data.iter().map(|v| v.conj()).collect::<Vec<_>>(),
+        )
+    }
+
+    // conjugate transpose, the complex counterpart of a plain transpose
+    pub fn hermitian_transpose(&self) -> Self {
+        self.transpose().conjugate()
     }
 }
 
-impl<T> Mul for Matrix<T>
+impl<T> Matrix<T>
 where
-    T: Copy + Default + Add<Output = T> + AddAssign + Mul<Output = T> + Debug + Send + 'static,
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<Output = T>,
 {
-    type Output = Self;
+    /// Exact closed-form determinant for 1x1, 2x2 (`ad - bc`) and 3x3 (rule of Sarrus)
+    /// matrices, usable with integer element types. Larger sizes are an error.
+    pub fn determinant_small(&self) -> Result<T> {
+        self.check_shape()?;
+        if self.rows != self.cols {
+            return Err(anyhow!("Determinant requires a square matrix"));
+        }
+        let m = &self.data;
+        match self.rows {
+            1 => Ok(m[0]),
+            2 => Ok(m[0] * m[3] - m[1] * m[2]),
+            3 => Ok(m[0] * m[4] * m[8] + m[1] * m[5] * m[6] + m[2] * m[3] * m[7]
+                - m[2] * m[4] * m[6]
+                - m[0] * m[5] * m[7]
+                - m[1] * m[3] * m[8]),
+            n => Err(anyhow!(
+                "No closed-form determinant for a {}x{} matrix",
+                n,
+                n
+            )),
+        }
+    }
+}
 
-    fn mul(self, rhs: Self) -> Self::Output {
-        multiply(&self, &rhs).expect("Matrix multiplication failed")
+impl<T: PartialEq> Matrix<T> {
+    pub fn is_symmetric(&self) -> bool {
+        self.rows == self.cols && self.first_asymmetry().is_none()
+    }
+
+    // first (i, j) with i < j, in row-major order, where self[i][j] != self[j][i];
+    // None for symmetric and non-square matrices
+    pub fn first_asymmetry(&self) -> Option<(usize, usize)> {
+        if self.rows != self.cols {
+            return None;
+        }
+        let n = self.rows;
+        (0..n)
+            .flat_map(|i| (i + 1..n).map(move |j| (i, j)))
+            .find(|&(i, j)| self.data[i * n + j] != self.data[j * n + i])
+    }
+
+    /// Every pair `(i, j)` with `i < j` whose rows are equal, in ascending order. Compares
+    /// all pairs; `duplicate_rows_hashed` is near-linear for hashable element types.
+    pub fn duplicate_rows(&self) -> Vec<(usize, usize)> {
+        let row = |i: usize| &self.data[i * self.cols..(i + 1) * self.cols];
+        let mut pairs = Vec::new();
+        for i in 0..self.rows {
+            for j in i + 1..self.rows {
+                if row(i) == row(j) {
+                    pairs.push((i, j));
+                }
+            }
+        }
+        pairs
     }
 }
 
-pub fn multiply<T>(a: &Matrix<T>, b: &Matrix<T>) -> Result<Matrix<T>>
-where
-    T: Copy + Default + Add<Output = T> + AddAssign + Mul<Output = T> + Debug + Send + 'static,
-{
-    if a.cols != b.rows {
-        return Err(anyhow!("Incompatible matrix dimensions"));
+impl<T: Eq + Hash> Matrix<T> {
+    // same result as `duplicate_rows`, grouping rows by hash instead of comparing all pairs
+    pub fn duplicate_rows_hashed(&self) -> Vec<(usize, usize)> {
+        let mut groups: HashMap<&[T], Vec<usize>> = HashMap::new();
+        for i in 0..self.rows {
+            groups
+                .entry(&self.data[i * self.cols..(i + 1) * self.cols])
+                .or_default()
+                .push(i);
+        }
+        let mut pairs = groups
+            .values()
+            .flat_map(|idx| {
+                idx.iter()
+                    .enumerate()
+                    .flat_map(move |(k, &i)| idx[k + 1..].iter().map(move |&j| (i, j)))
+            })
+            .collect::<Vec<_>>();
+        pairs.sort_unstable();
+        pairs
     }
+}
 
-    let senders = (0..NUM_THREADS)
-        .map(|_| {
-            let (tx, rx) = mpsc::channel::<Msg<T>>();
+impl<T: PartialOrd> Matrix<T> {
+    /// Column index of the largest element in each row, e.g. the predicted label for each
+    /// row of class scores. Ties go to the first maximum; a row without columns yields 0.
+    pub fn argmax_rows(&self) -> Vector<usize> {
+        let data = (0..self.rows)
+            .map(|i| argmax((0..self.cols).map(|j| &self.data[i * self.cols + j])))
+            .collect::<Vec<_>>();
+        Vector::new(data)
+    }
 
-            thread::spawn(move || {
-                for msg in rx {
-                    let value = dot_product(msg.input.row, msg.input.col).unwrap();
-                    msg.sender
-                        .send(MsgOutput {
-                            idx: msg.input.idx,
-                            value,
-                        })
-                        .unwrap();
+    // row index of the largest element in each column, see `argmax_rows`
+    pub fn argmax_cols(&self) -> Vector<usize> {
+        let data = (0..self.cols)
+            .map(|j| argmax((0..self.rows).map(|i| &self.data[i * self.cols + j])))
+            .collect::<Vec<_>>();
+        Vector::new(data)
+    }
+}
+
+fn argmax<'a, T: PartialOrd + 'a>(values: impl Iterator<Item = &'a T>) -> usize {
+    let mut best: Option<(usize, &T)> = None;
+    for (idx, v) in values.enumerate() {
+        if best.is_none_or(|(_, max)| v > max) {
+            best = Some((idx, v));
+        }
+    }
+    best.map_or(0, |(idx, _)| idx)
+}
+
+impl<T: Copy + Default> Matrix<T> {
+    // sum of the main diagonal of a square matrix; integers wrap on overflow like `multiply`
+    pub fn trace(&self) -> Result<T>
+    where
+        T: WrappingAdd,
+    {
+        if self.rows != self.cols {
+            return Err(anyhow!("Trace requires a square matrix"));
+        }
+        Ok(self
+            .diagonal()
+            .into_iter()
+            .fold(T::default(), |acc, v| acc.wrapping_add(v)))
+    }
+
+    pub fn row_sums(&self) -> Vector<T>
+    where
+        T: Add<Output = T>,
+    {
+        Vector::new(
+            (0..self.rows)
+                .map(|i| {
+                    self.data[i * self.cols..(i + 1) * self.cols]
+                        .iter()
+                        .fold(T::default(), |acc, &v| acc + v)
+                })
+                .collect::<Vec<_>>(),
+        )
+    }
+
+    pub fn col_sums(&self) -> Vector<T>
+    where
+        T: Add<Output = T>,
+    {
+        let mut sums = vec![T::default(); self.cols];
+        for row in self.data.chunks(self.cols.max(1)) {
+            for (sum, &v) in sums.iter_mut().zip(row) {
+                *sum = *sum + v;
+            }
+        }
+        Vector::new(sums)
+    }
+
+    // keep the elements above the diagonal (and on it, if asked), zero the rest
+    pub fn upper_triangular(&self, include_diagonal: bool) -> Matrix<T> {
+        self.keep_where(|i, j| j > i || (include_diagonal && i == j))
+    }
+
+    // keep the elements below the diagonal (and on it, if asked), zero the rest
+    pub fn lower_triangular(&self, include_diagonal: bool) -> Matrix<T> {
+        self.keep_where(|i, j| j < i || (include_diagonal && i == j))
+    }
+
+    fn keep_where(&self, keep: impl Fn(usize, usize) -> bool) -> Matrix<T> {
+        Matrix::from_fn(self.rows, self.cols, |i, j| {
+            if keep(i, j) {
+                self.data[i * self.cols + j]
+            } else {
+                T::default()
+            }
+        })
+    }
+
+    // n x n matrix with `value` on the diagonal, e.g. lambda * I for regularization
+    pub fn scaled_identity(n: usize, value: T) -> Self {
+        let mut data = vec![T::default(); n * n];
+        for i in 0..n {
+            data[i * n + i] = value;
+        }
+        Self {
+            rows: n,
+            cols: n,
+            data,
+        }
+    }
+
+    // place the blocks along the diagonal of an otherwise zero (default) matrix
+    pub fn block_diag(blocks: &[Matrix<T>]) -> Self {
+        let rows = blocks.iter().map(|b| b.rows).sum::<usize>();
+        let cols = blocks.iter().map(|b| b.cols).sum::<usize>();
+        let mut data = vec![T::default(); rows * cols];
+        let (mut r0, mut c0) = (0, 0);
+        for block in blocks {
+            for i in 0..block.rows {
+                let dst = (r0 + i) * cols + c0;
+                data[dst..dst + block.cols]
+                    .copy_from_slice(&block.data[i * block.cols..(i + 1) * block.cols]);
+            }
+            r0 += block.rows;
+            c0 += block.cols;
+        }
+        Self { rows, cols, data }
+    }
+}
+
+impl<T: Copy + Default + From<u8>> Matrix<T> {
+    pub fn identity(n: usize) -> Self {
+        Self::scaled_identity(n, T::from(1))
+    }
+
+    // NumPy-style name for `identity`
+    pub fn eye(n: usize) -> Self {
+        Self::identity(n)
+    }
+}
+
+impl<T: Copy + Mul<Output = T> + From<u8>> Matrix<T> {
+    /// Raise every element to the integer power `exp` (the Hadamard power), using
+    /// exponentiation by squaring. This is not the matrix power `A^exp`, which is repeated
+    /// matrix multiplication. Overflow behaves like the element type's `*`.
+    pub fn pow_elements(&self, exp: u32) -> Matrix<T> {
+        self.map(|&v| {
+            let (mut base, mut exp, mut acc) = (v, exp, T::from(1));
+            while exp > 0 {
+                if exp & 1 == 1 {
+                    acc = acc * base;
                 }
-            });
-            tx
+                exp >>= 1;
+                if exp > 0 {
+                    base = base * base;
+                }
+            }
+            acc
         })
-        .collect::<Vec<_>>();
+    }
+}
 
-    let mut result = vec![T::default(); a.rows * b.cols];
-    let mut receivers = Vec::with_capacity(a.rows * b.cols);
-    for i in 0..a.rows {
-        for j in 0..b.cols {
-            let col_data = b.data[j..]
-                .iter()
-                .step_by(b.cols)
-                .copied()
-                .collect::<Vec<_>>();
-            let idx = i * b.cols + j;
-            let row = Vector::new(&a.data[i * a.cols..(i + 1) * a.cols]);
-            let col = Vector::new(col_data);
-            let input = MsgInput::new(idx, row, col);
-            let (tx, rx) = oneshot::channel();
-            senders[idx % NUM_THREADS]
-                .send(Msg::new(input, tx))
-                .unwrap();
-            receivers.push(rx);
+impl<T: Copy + Mul<Output = T>> Matrix<T> {
+    // multiply row i by weights[i], i.e. diag(weights) * self without building the diagonal
+    pub fn scale_rows(&self, weights: &Vector<T>) -> Result<Matrix<T>> {
+        if weights.len() != self.rows {
+            return Err(anyhow!(
+                "Expected {} row weights, got {}",
+                self.rows,
+                weights.len()
+            ));
         }
+        self.check_shape()?;
+        Ok(Matrix::from_fn(self.rows, self.cols, |i, j| {
+            self.data[i * self.cols + j] * weights[i]
+        }))
     }
 
-    for rx in receivers {
-        let output = rx.recv().unwrap();
-        result[output.idx] = output.value;
+    // multiply column j by weights[j], i.e. self * diag(weights)
+    pub fn scale_cols(&self, weights: &Vector<T>) -> Result<Matrix<T>> {
+        if weights.len() != self.cols {
+            return Err(anyhow!(
+                "Expected {} column weights, got {}",
+                self.cols,
+                weights.len()
+            ));
+        }
+        self.check_shape()?;
+        Ok(Matrix::from_fn(self.rows, self.cols, |i, j| {
+            self.data[i * self.cols + j] * weights[j]
+        }))
     }
 
-    Ok(Matrix::new(a.rows, b.cols, result))
+    /// Kronecker product: every element `a[i][j]` replaced by the block `a[i][j] * other`,
+    /// giving a `(rows * other.rows) x (cols * other.cols)` matrix.
+    pub fn kronecker(&self, other: &Matrix<T>) -> Matrix<T> {
+        let (br, bc) = (other.rows, other.cols);
+        Matrix::from_fn(self.rows * br, self.cols * bc, |r, c| {
+            self.data[(r / br) * self.cols + c / bc] * other.data[(r % br) * bc + c % bc]
+        })
+    }
 }
 
-impl<T> fmt::Display for Matrix<T>
+impl<T> Matrix<T>
 where
-    T: fmt::Display,
+    T: Copy + Default + From<u8> + Add<Output = T> + Mul<Output = T>,
 {
-    // display a 2x3 as {1 2 3, 4 5 6}, 3x2 as {1 2, 3 4, 5 6}
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{{")?;
-        for i in 0..self.rows {
-            for j in 0..self.cols {
-                write!(f, "{}", self.data[i * self.cols + j])?;
-                if j < self.cols - 1 {
-                    write!(f, " ")?;
-                }
-            }
-            if i < self.rows - 1 {
-                write!(f, ", ")?;
-            }
+    /// Kronecker sum `A ⊗ I_m + I_n ⊗ B` of a square n x n `A` and square m x m `B`, as
+    /// used for Sylvester and Lyapunov equations.
+    pub fn kronecker_sum(&self, other: &Matrix<T>) -> Result<Matrix<T>> {
+        if self.rows != self.cols || other.rows != other.cols {
+            return Err(anyhow!("Kronecker sum requires square matrices"));
         }
-        write!(f, "}}")?;
+        self.check_shape()?;
+        other.check_shape()?;
+        let left = self.kronecker(&Matrix::identity(other.rows));
+        let right = Matrix::identity(self.rows).kronecker(other);
+        left.add(&right)
+    }
+}
 
-        Ok(())
+impl<T: Copy + Add<Output = T>> Matrix<T> {
+    // element-wise sum of two matrices of the same shape
+    pub fn add(&self, other: &Matrix<T>) -> Result<Matrix<T>> {
+        if self.rows != other.rows || self.cols != other.cols {
+            return Err(anyhow!("Incompatible matrix dimensions"));
+        }
+        self.check_shape()?;
+        other.check_shape()?;
+        Ok(Matrix {
+            rows: self.rows,
+            cols: self.cols,
+            data: self
+                .data
+                .iter()
+                .zip(&other.data)
+                .map(|(&a, &b)| a + b)
+                .collect(),
+        })
     }
 }
 
-impl<T> fmt::Debug for Matrix<T>
+impl<T> Matrix<T>
 where
-    T: fmt::Display,
+    T: Numeric,
 {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Matrix({}, {}, {})", self.rows, self.cols, self)
+    // matrix-vector product, computed on the calling thread
+    pub fn mul_vector(&self, v: &Vector<T>) -> Result<Vector<T>> {
+        if self.cols != v.len() {
+            return Err(anyhow!("Incompatible matrix and vector dimensions"));
+        }
+        let data = (0..self.rows)
+            .map(|i| dot_product_slice(&self.row_view(i)?, v))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Vector::new(data))
+    }
+
+    /// The single transform that applies `applied_first` and then `self`, i.e.
+    /// `self * applied_first`. `applied_first` must output vectors of the dimension `self`
+    /// takes as input (`applied_first.rows == self.cols`).
+    pub fn compose(&self, applied_first: &Matrix<T>) -> Result<Matrix<T>> {
+        if applied_first.rows != self.cols {
+            return Err(anyhow!(
+                "Cannot compose: first transform outputs {} dimensions, second expects {}",
+                applied_first.rows,
+                self.cols
+            ));
+        }
+        multiply(self, applied_first)
+    }
+
+    // self^T * other, without the caller having to build the transpose
+    pub fn transpose_multiply(&self, other: &Matrix<T>) -> Result<Matrix<T>> {
+        multiply(&self.transpose(), other)
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+impl Matrix<f64> {
+    /// A matrix of uniform `[0, 1)` values from a `StdRng` seeded with `seed`. The same
+    /// seed always yields the same matrix.
+    #[cfg(feature = "rand")]
+    pub fn random_seeded(rows: usize, cols: usize, seed: u64) -> Self {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
 
-    #[test]
-    fn test_matrix_multiply() -> Result<()> {
-        let a = Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]);
-        let b = Matrix::new(3, 2, vec![1, 2, 3, 4, 5, 6]);
-        let c = a * b;
-        assert_eq!(c.rows, 2);
-        assert_eq!(c.cols, 2);
-        assert_eq!(c.data, vec![22, 28, 49, 64]);
-        assert_eq!(format!("{}", c), "{22 28, 49 64}");
+        let mut rng = StdRng::seed_from_u64(seed);
+        Self::from_fn(rows, cols, |_, _| rng.gen::<f64>())
+    }
 
-        Ok(())
+    /// Raise every element to `exponent` (the Hadamard power), e.g. for gamma correction.
+    /// Element-wise, unlike the matrix power `A^n` of repeated matrix multiplication.
+    pub fn powf_elements(&self, exponent: f64) -> Matrix<f64> {
+        self.map(|v| v.powf(exponent))
     }
 
-    #[test]
-    fn test_matrix_display() -> Result<()> {
-        let a = Matrix::new(2, 2, vec![1, 2, 3, 4]);
-        let b = Matrix::new(2, 2, vec![1, 2, 3, 4]);
-        let c = multiply(&a, &b)?;
-        assert_eq!(c.data, vec![7, 10, 15, 22]);
-        assert_eq!(format!("{}", c), "{7 10, 15 22}");
+    /// The nearest symmetric matrix `(A + A^T) / 2`, e.g. to clean up a covariance matrix
+    /// that drifted from symmetry through rounding before a Cholesky factorization.
+    pub fn symmetrize(&self) -> Result<Matrix<f64>> {
+        if self.rows != self.cols {
+            return Err(anyhow!("Symmetrize requires a square matrix"));
+        }
+        self.add(&self.transpose())?.div_scalar(2.0)
+    }
+
+    /// Zero every element whose absolute value is strictly below `min_abs`, e.g. before
+    /// converting to sparse storage. Elements exactly at `min_abs` are kept.
+    pub fn threshold(&self, min_abs: f64) -> Matrix<f64> {
+        self.map(|&v| if v.abs() < min_abs { 0.0 } else { v })
+    }
+
+    /// Count the elements into `bins` equal-width bins spanning `[min, max]`; values outside
+    /// the range (and NaN) are clamped into the edge bins, so the counts always sum to the
+    /// element count. Large matrices are counted in per-thread partial histograms that are
+    /// merged at the end. If `max <= min` everything lands in the first bin.
+    pub fn histogram(&self, bins: usize, min: f64, max: f64) -> Vec<usize> {
+        if bins == 0 {
+            return Vec::new();
+        }
+        let width = (max - min) / bins as f64;
+        let count = |values: &[f64]| {
+            let mut counts = vec![0; bins];
+            for &v in values {
+                // `as` saturates, so values below min (and NaN) go to bin 0
+                let bin = if width > 0.0 {
+                    ((v - min) / width) as usize
+                } else {
+                    0
+                };
+                counts[bin.min(bins - 1)] += 1;
+            }
+            counts
+        };
+
+        let chunk = self
+            .data
+            .len()
+            .div_ceil(NUM_THREADS)
+            .max(MIN_WORK_PER_THREAD);
+        thread::scope(|s| {
+            let handles = self
+                .data
+                .chunks(chunk)
+                .map(|values| s.spawn(move || count(values)))
+                .collect::<Vec<_>>();
+            let mut total = vec![0; bins];
+            for handle in handles {
+                let partial = handle.join().expect("histogram worker panicked");
+                for (t, p) in total.iter_mut().zip(partial) {
+                    *t += p;
+                }
+            }
+            total
+        })
+    }
+
+    /// Symmetric `rows x rows` matrix of Euclidean distances between every pair of rows,
+    /// zero on the diagonal. Each distance is computed once, with the rows spread across
+    /// threads for large inputs, and mirrored into the lower triangle.
+    pub fn pairwise_distances(&self) -> Result<Matrix<f64>> {
+        self.check_shape()?;
+        let n = self.rows;
+        let mut result = vec![0.0; n * n];
+        if n > 0 {
+            let threads = recommended_threads(n, n, self.cols);
+            let chunk = n.div_ceil(threads) * n;
+            thread::scope(|s| {
+                let handles = result
+                    .chunks_mut(chunk)
+                    .enumerate()
+                    .map(|(w, cells)| {
+                        s.spawn(move || -> Result<()> {
+                            for (offset, row) in cells.chunks_mut(n).enumerate() {
+                                let i = w * chunk / n + offset;
+                                let a = self.row_view(i)?;
+                                for (j, cell) in row.iter_mut().enumerate().skip(i + 1) {
+                                    *cell = euclidean_distance(&a, &self.row_view(j)?)?;
+                                }
+                            }
+                            Ok(())
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                handles.into_iter().try_for_each(|handle| {
+                    handle
+                        .join()
+                        .map_err(|e| anyhow!("Thread join error: {:?}", e))?
+                })
+            })?;
+            for i in 0..n {
+                for j in 0..i {
+                    result[i * n + j] = result[j * n + i];
+                }
+            }
+        }
+        Ok(Matrix {
+            rows: n,
+            cols: n,
+            data: result,
+        })
+    }
+
+    /// Scale every row to unit L2 norm, e.g. so that dot products of rows are cosine
+    /// similarities. All-zero rows have no direction and are left as zeros.
+    pub fn l2_normalize_rows(&self) -> Result<Matrix<f64>> {
+        self.check_shape()?;
+        let mut data = Vec::with_capacity(self.data.len());
+        for i in 0..self.rows {
+            let row = Vector::new(self.row_view(i)?.to_vec());
+            if row.norm() == 0.0 {
+                data.extend_from_slice(&row);
+            } else {
+                data.extend_from_slice(&row.normalize()?);
+            }
+        }
+        Ok(Matrix {
+            rows: self.rows,
+            cols: self.cols,
+            data,
+        })
+    }
+
+    pub fn div_scalar(&self, divisor: f64) -> Result<Matrix<f64>> {
+        if divisor == 0.0 {
+            return Err(anyhow!("Division by zero"));
+        }
+        Ok(self.map(|v| v / divisor))
+    }
+
+    // element-wise 1/x
+    pub fn reciprocal(&self) -> Result<Matrix<f64>> {
+        if let Some(idx) = self.data.iter().position(|&v| v == 0.0) {
+            return Err(anyhow!(
+                "Reciprocal of zero at ({}, {})",
+                idx / self.cols,
+                idx % self.cols
+            ));
+        }
+        Ok(self.map(|v| 1.0 / v))
+    }
+
+    /// Largest absolute element-wise difference from `reference`, e.g. how far an optimized
+    /// multiply drifts from a naive one. 0.0 for empty matrices; NaN if any difference is.
+    pub fn max_abs_error(&self, reference: &Matrix<f64>) -> Result<f64> {
+        if self.rows != reference.rows || self.cols != reference.cols {
+            return Err(anyhow!(
+                "Shape mismatch: {}x{} vs {}x{}",
+                self.rows,
+                self.cols,
+                reference.rows,
+                reference.cols
+            ));
+        }
+        self.check_shape()?;
+        reference.check_shape()?;
+        Ok(self
+            .data
+            .iter()
+            .zip(&reference.data)
+            .map(|(a, b)| (a - b).abs())
+            .fold(0.0, |max, d| if d > max || d.is_nan() { d } else { max }))
+    }
+
+    pub fn approx_eq(&self, other: &Matrix<f64>, epsilon: f64) -> bool {
+        self.rows == other.rows
+            && self.cols == other.cols
+            && self
+                .data
+                .iter()
+                .zip(&other.data)
+                .all(|(a, b)| (a - b).abs() <= epsilon)
+    }
+
+    /// Stationary distribution of a row-stochastic matrix (a Markov chain transition
+    /// matrix): starting from the uniform distribution, repeatedly apply the chain until
+    /// no entry moves by more than `tol`. Errors if the rows don't sum to 1 or the chain
+    /// hasn't converged within `iterations` steps (e.g. a periodic chain).
+    pub fn steady_state(&self, iterations: usize, tol: f64) -> Result<Vector<f64>> {
+        if self.rows != self.cols || self.rows == 0 {
+            return Err(anyhow!("Steady state requires a non-empty square matrix"));
+        }
+        if let Some(i) = self.first_non_stochastic_row(1e-9) {
+            return Err(anyhow!("Row {} is not a probability distribution", i));
+        }
+
+        // a distribution is a row vector: pi' = pi * P = P^T * pi
+        let transposed = self.transpose();
+        let mut pi = Vector::new(vec![1.0 / self.rows as f64; self.rows]);
+        for _ in 0..iterations {
+            let next = transposed.mul_vector(&pi)?;
+            let delta = next
+                .iter()
+                .zip(pi.iter())
+                .map(|(a, b)| (a - b).abs())
+                .fold(0.0, f64::max);
+            pi = next;
+            if delta <= tol {
+                return Ok(pi);
+            }
+        }
+        Err(anyhow!("Did not converge within {} iterations", iterations))
+    }
+
+    /// Estimate the spectral radius (the largest eigenvalue magnitude) by power iteration:
+    /// repeatedly apply the matrix to a unit vector and measure how much it grows, for at
+    /// most `iterations` steps or until the estimate settles. Converges when a single
+    /// eigenvalue dominates in magnitude; with a complex pair or a `-λ, λ` pair on top the
+    /// iteration oscillates and the result is only an estimate.
+    pub fn spectral_radius(&self, iterations: usize) -> Result<f64> {
+        if self.rows != self.cols || self.rows == 0 {
+            return Err(anyhow!(
+                "Spectral radius requires a non-empty square matrix"
+            ));
+        }
+        if iterations == 0 {
+            return Err(anyhow!("Spectral radius needs at least one iteration"));
+        }
+        // a non-uniform start is less likely to be orthogonal to the dominant eigenvector
+        let mut v = Vector::new((1..=self.rows).map(|i| i as f64).collect::<Vec<_>>());
+        v.normalize_in_place()?;
+        let mut estimate = 0.0;
+        for _ in 0..iterations {
+            let mut next = self.mul_vector(&v)?;
+            let growth = next.norm();
+            if growth == 0.0 {
+                return Ok(0.0);
+            }
+            next.normalize_in_place()?;
+            v = next;
+            let settled = (growth - estimate).abs() <= 1e-12 * growth;
+            estimate = growth;
+            if settled {
+                break;
+            }
+        }
+        Ok(estimate)
+    }
+
+    /// Sinkhorn-Knopp: alternately rescale rows and columns to sum to 1 until every row
+    /// sum is within `tol` of 1 after a column pass, giving a doubly stochastic matrix.
+    /// Errors on a non-square matrix, negative entries, an all-zero row or column, or no
+    /// convergence within `iterations` rounds (e.g. a zero pattern that has no doubly
+    /// stochastic scaling).
+    pub fn sinkhorn(&self, iterations: usize, tol: f64) -> Result<Matrix<f64>> {
+        self.check_shape()?;
+        if self.rows != self.cols {
+            return Err(anyhow!("Sinkhorn requires a square matrix"));
+        }
+        if let Some(idx) = self.data.iter().position(|&v| v < 0.0) {
+            return Err(anyhow!(
+                "Negative entry at ({}, {})",
+                idx / self.cols,
+                idx % self.cols
+            ));
+        }
+        let inverse = |sums: Vector<f64>, what: &str| -> Result<Vector<f64>> {
+            if let Some(k) = sums.iter().position(|&s| s == 0.0) {
+                return Err(anyhow!("{} {} sums to zero", what, k));
+            }
+            Ok(Vector::new(
+                sums.iter().map(|s| 1.0 / s).collect::<Vec<_>>(),
+            ))
+        };
+
+        let mut m = self.scale_rows(&inverse(self.row_sums(), "Row")?)?;
+        for _ in 0..iterations {
+            m = m.scale_cols(&inverse(m.col_sums(), "Column")?)?;
+            let row_sums = m.row_sums();
+            if row_sums.iter().all(|s| (s - 1.0).abs() <= tol) {
+                return Ok(m);
+            }
+            m = m.scale_rows(&inverse(row_sums, "Row")?)?;
+        }
+        Err(anyhow!("Did not converge within {} iterations", iterations))
+    }
+
+    // every entry non-negative and every row summing to 1 within `epsilon`
+    pub fn is_row_stochastic(&self, epsilon: f64) -> bool {
+        self.first_non_stochastic_row(epsilon).is_none()
+    }
+
+    // row stochastic, and every column also sums to 1 within `epsilon`
+    pub fn is_doubly_stochastic(&self, epsilon: f64) -> bool {
+        self.is_row_stochastic(epsilon)
+            && (0..self.cols).all(|j| {
+                let sum = (0..self.rows)
+                    .map(|i| self.data[i * self.cols + j])
+                    .sum::<f64>();
+                (sum - 1.0).abs() <= epsilon
+            })
+    }
+
+    fn first_non_stochastic_row(&self, epsilon: f64) -> Option<usize> {
+        (0..self.rows).find(|&i| {
+            let row = &self.data[i * self.cols..(i + 1) * self.cols];
+            row.iter().any(|&v| v < 0.0) || (row.iter().sum::<f64>() - 1.0).abs() > epsilon
+        })
+    }
+
+    // closed form up to 3x3, LU decomposition with partial pivoting beyond that
+    pub fn determinant(&self) -> Result<f64> {
+        self.check_shape()?;
+        if self.rows != self.cols {
+            return Err(anyhow!("Determinant requires a square matrix"));
+        }
+        let n = self.rows;
+        match n {
+            0 => return Ok(1.0),
+            1..=3 => return self.determinant_small(),
+            _ => {}
+        }
+
+        let mut lu = self.data.clone();
+        let mut det = 1.0;
+        for k in 0..n {
+            let pivot = (k..n)
+                .max_by(|&a, &b| lu[a * n + k].abs().total_cmp(&lu[b * n + k].abs()))
+                .unwrap_or(k);
+            if lu[pivot * n + k] == 0.0 {
+                return Ok(0.0);
+            }
+            if pivot != k {
+                for j in 0..n {
+                    lu.swap(k * n + j, pivot * n + j);
+                }
+                det = -det;
+            }
+            det *= lu[k * n + k];
+            for i in k + 1..n {
+                let factor = lu[i * n + k] / lu[k * n + k];
+                for j in k + 1..n {
+                    lu[i * n + j] -= factor * lu[k * n + j];
+                }
+            }
+        }
+        Ok(det)
+    }
+
+    /// Inverse by Gauss-Jordan elimination with partial pivoting. Errors on a non-square
+    /// or (numerically) singular matrix, i.e. when a pivot is negligible relative to the
+    /// largest entry.
+    pub fn inverse(&self) -> Result<Matrix<f64>> {
+        if self.rows != self.cols {
+            return Err(anyhow!("Inverse requires a square matrix"));
+        }
+        self.check_shape()?;
+        let n = self.rows;
+        let scale = self.data.iter().fold(0.0, |m: f64, v| m.max(v.abs()));
+        let tol = scale * n as f64 * f64::EPSILON;
+
+        // reduce [A | I] to [I | A^-1]
+        let mut a = self.data.clone();
+        let mut inv = Matrix::<f64>::identity(n).data;
+        for k in 0..n {
+            let pivot = (k..n)
+                .max_by(|&x, &y| a[x * n + k].abs().total_cmp(&a[y * n + k].abs()))
+                .unwrap_or(k);
+            if a[pivot * n + k].abs() <= tol {
+                return Err(anyhow!("Matrix is singular"));
+            }
+            if pivot != k {
+                for j in 0..n {
+                    a.swap(k * n + j, pivot * n + j);
+                    inv.swap(k * n + j, pivot * n + j);
+                }
+            }
+            let p = a[k * n + k];
+            for j in 0..n {
+                a[k * n + j] /= p;
+                inv[k * n + j] /= p;
+            }
+            for i in (0..n).filter(|&i| i != k) {
+                let factor = a[i * n + k];
+                if factor != 0.0 {
+                    for j in 0..n {
+                        a[i * n + j] -= factor * a[k * n + j];
+                        inv[i * n + j] -= factor * inv[k * n + j];
+                    }
+                }
+            }
+        }
+        Ok(Matrix {
+            rows: n,
+            cols: n,
+            data: inv,
+        })
+    }
+
+    /// Moore-Penrose pseudoinverse of a full-rank matrix: `(A^T A)^-1 A^T` when tall
+    /// (full column rank), `A^T (A A^T)^-1` when wide (full row rank). Squaring the
+    /// matrix squares its condition number, so this suits well-conditioned problems; a
+    /// rank-deficient matrix is an error.
+    pub fn pseudoinverse(&self) -> Result<Matrix<f64>> {
+        let rank_deficient = |e: anyhow::Error| anyhow!("Matrix is rank deficient: {}", e);
+        if self.rows >= self.cols {
+            let gram = self
+                .transpose_multiply(self)?
+                .inverse()
+                .map_err(rank_deficient)?;
+            multiply(&gram, &self.transpose())
+        } else {
+            let t = self.transpose();
+            let gram = t
+                .transpose_multiply(&t)?
+                .inverse()
+                .map_err(rank_deficient)?;
+            multiply(&t, &gram)
+        }
+    }
+
+    /// Least-squares solution of `self * x ≈ b`, minimizing `|self * x - b|`, via the
+    /// pseudoinverse. For a wide (underdetermined) matrix this is the minimum-norm
+    /// solution. Errors if `b` doesn't have one entry per row or the matrix isn't full rank.
+    pub fn lstsq(&self, b: &Vector<f64>) -> Result<Vector<f64>> {
+        if b.len() != self.rows {
+            return Err(anyhow!(
+                "Expected a right-hand side of length {}, got {}",
+                self.rows,
+                b.len()
+            ));
+        }
+        self.pseudoinverse()?.mul_vector(b)
+    }
+
+    /// Z-score every column: subtract its mean and divide by its (population) standard
+    /// deviation. A zero-variance column has nothing to scale by and is only centered, so
+    /// it comes out as all zeros. Errors on a matrix without rows.
+    pub fn standardize_columns(&self) -> Result<Matrix<f64>> {
+        let mut result = self.data.clone();
+        for j in 0..self.cols {
+            let column = Vector::new(
+                (0..self.rows)
+                    .map(|i| self.data[i * self.cols + j])
+                    .collect::<Vec<_>>(),
+            );
+            let (mean, variance) = column.mean_variance()?;
+            let std_dev = variance.sqrt();
+            for i in 0..self.rows {
+                let v = &mut result[i * self.cols + j];
+                *v -= mean;
+                if std_dev > 0.0 {
+                    *v /= std_dev;
+                }
+            }
+        }
+        Ok(Matrix {
+            rows: self.rows,
+            cols: self.cols,
+            data: result,
+        })
+    }
+
+    /// Index and Euclidean norm of the column with the largest norm, the first one on ties.
+    /// Errors on a matrix without columns.
+    pub fn max_norm_column(&self) -> Result<(usize, f64)> {
+        let mut best: Option<(usize, f64)> = None;
+        for j in 0..self.cols {
+            let mut col = Vec::with_capacity(self.rows);
+            self.copy_col_into(j, &mut col)?;
+            let norm = Vector::new(col).norm();
+            if best.is_none_or(|(_, max)| norm > max) {
+                best = Some((j, norm));
+            }
+        }
+        best.ok_or_else(|| anyhow!("Max norm column of a matrix without columns"))
+    }
+
+    /// Check whether the columns are orthonormal, i.e. `self^T * self` is the identity
+    /// within `epsilon`.
+    pub fn is_orthogonal(&self, epsilon: f64) -> bool {
+        match self.transpose_multiply(self) {
+            Ok(product) => product.approx_eq(&Matrix::identity(self.cols), epsilon),
+            Err(_) => false,
+        }
+    }
+}
+
+pub struct MsgInput<T> {
+    idx: usize,
+    row: Vector<T>,
+    col: Vector<T>,
+}
+
+impl<T> MsgInput<T> {
+    pub fn new(idx: usize, row: Vector<T>, col: Vector<T>) -> Self {
+        Self { idx, row, col }
+    }
+}
+
+pub struct MsgOutput<T> {
+    idx: usize,
+    value: Result<T>,
+}
+
+// computes one output cell from its row and column
+type Kernel<T> = Arc<dyn Fn(Vector<T>, Vector<T>) -> Result<T> + Send + Sync>;
+
+// what one multiply worker did: how many cells it computed and the time spent on them
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct WorkerStats {
+    pub cells: usize,
+    pub busy: Duration,
+}
+
+pub struct Msg<T> {
+    input: MsgInput<T>,
+    sender: Reply<T>,
+}
+
+impl<T> Msg<T> {
+    pub fn new(input: MsgInput<T>, sender: Reply<T>) -> Self {
+        Self { input, sender }
+    }
+}
+
+struct AbortOnDrop(CancellationToken);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.cancel();
+    }
+}
+
+// where a worker delivers a finished cell: its own oneshot, or a channel shared by all
+// cells when the caller consumes results in completion order
+pub enum Reply<T> {
+    Cell(oneshot::Sender<MsgOutput<T>>),
+    Stream(mpsc::Sender<MsgOutput<T>>),
+}
+
+impl<T> Reply<T> {
+    // the caller may already have returned, ignore send errors
+    fn send(self, output: MsgOutput<T>) {
+        let _ = match self {
+            Reply::Cell(tx) => tx.send(output).map_err(|_| ()),
+            Reply::Stream(tx) => tx.send(output).map_err(|_| ()),
+        };
+    }
+}
+
+impl<T> Mul for Matrix<T>
+where
+    T: Numeric,
+{
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self::Output {
+        multiply(&self, &rhs).expect("Matrix multiplication failed")
+    }
+}
+
+/// Threaded matrix product. Integer cells wrap on overflow in debug and release builds
+/// alike (see `FusedMulAdd`); use `multiply_with_policy` to saturate or fail instead.
+pub fn multiply<T>(a: &Matrix<T>, b: &Matrix<T>) -> Result<Matrix<T>>
+where
+    T: Numeric,
+{
+    MultiplyConfig::new().build().multiply(a, b)
+}
+
+/// Like `multiply`, but yields each output cell as `(row, col, value)` as soon as a
+/// worker finishes it, e.g. to render partial results. Cells arrive in completion order,
+/// not row-major order.
+pub fn multiply_streaming<T>(
+    a: &Matrix<T>,
+    b: &Matrix<T>,
+) -> Result<impl Iterator<Item = (usize, usize, T)>>
+where
+    T: Numeric,
+{
+    MultiplyConfig::new().build().multiply_streaming(a, b)
+}
+
+// `multiply`, also reporting per-worker cell counts and busy time to diagnose imbalance
+pub fn multiply_with_stats<T>(a: &Matrix<T>, b: &Matrix<T>) -> Result<(Matrix<T>, Vec<WorkerStats>)>
+where
+    T: Numeric,
+{
+    MultiplyConfig::new().build().multiply_with_stats(a, b)
+}
+
+/// Like `multiply`, but stops early with a "cancelled" error once `token` is cancelled.
+/// Workers check the token before every cell; whatever was computed so far is discarded.
+pub fn multiply_cancellable<T>(
+    a: &Matrix<T>,
+    b: &Matrix<T>,
+    token: &CancellationToken,
+) -> Result<Matrix<T>>
+where
+    T: Numeric,
+{
+    MultiplyConfig::new()
+        .cancellation(token.clone())
+        .build()
+        .multiply(a, b)
+}
+
+/// `multiply`, recording the call into `metrics`: the elapsed microseconds are added to
+/// `{key}.elapsed_us` and `{key}.count` is incremented, whether or not the multiply
+/// succeeded. With a fixed-key backend like `AmapMetrics` both keys must be registered.
+pub fn multiply_timed<T>(
+    a: &Matrix<T>,
+    b: &Matrix<T>,
+    metrics: &impl Metrics,
+    key: &str,
+) -> Result<Matrix<T>>
+where
+    T: Numeric,
+{
+    let start = Instant::now();
+    let result = multiply(a, b);
+    let elapsed = start.elapsed().as_micros() as i64;
+    metrics.add(&format!("{}.elapsed_us", key), elapsed)?;
+    metrics.inc(&format!("{}.count", key))?;
+    result
+}
+
+/// What integer `multiply_with_policy` does when a cell overflows the element type.
+/// `Saturate` and `Error` look at the exact value of the cell, so a partial sum that
+/// overflows and comes back into range is fine. The plain `multiply` always wraps, the
+/// same as `Wrap`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Overflow {
+    #[default]
+    Wrap,
+    Saturate,
+    // fail with the coordinate of the overflowing cell
+    Error,
+}
+
+pub fn multiply_with_policy<T>(a: &Matrix<T>, b: &Matrix<T>, policy: Overflow) -> Result<Matrix<T>>
+where
+    T: Numeric + OverflowArith,
+{
+    MultiplyConfig::new()
+        .build()
+        .multiply_with_policy(a, b, policy)
+}
+
+/// Multiply `u64` matrices with every product and sum taken modulo `modulus`, so the
+/// result lies in `[0, modulus)` and never overflows. Errors when `modulus` is zero.
+pub fn multiply_mod(a: &Matrix<u64>, b: &Matrix<u64>, modulus: u64) -> Result<Matrix<u64>> {
+    MultiplyConfig::new().build().multiply_mod(a, b, modulus)
+}
+
+/// Multiply on a caller-supplied pool (a `ThreadPool`, a rayon pool, ...) instead of
+/// threads spawned by this crate. The output cells are split into contiguous ranges,
+/// one task each, and every task reports its range back over a oneshot channel.
+pub fn multiply_in_pool<T>(a: &Matrix<T>, b: &Matrix<T>, pool: &impl Spawner) -> Result<Matrix<T>>
+where
+    T: Numeric + Sync,
+{
+    a.check_shape()?;
+    b.check_shape()?;
+    if a.cols != b.rows {
+        return Err(anyhow!("Incompatible matrix dimensions"));
+    }
+
+    // tasks must be 'static, so they share owned copies; columns of b are rows of b^T
+    let total = a.rows * b.cols;
+    let a_rows = Arc::new(a.data.clone());
+    let b_cols = Arc::new(b.transpose().data);
+    let (inner, cols) = (a.cols, b.cols);
+    let receivers = cell_ranges(total, recommended_threads(a.rows, b.cols, a.cols))
+        .into_iter()
+        .filter(|cells| !cells.is_empty())
+        .map(|cells| {
+            let (a_rows, b_cols) = (Arc::clone(&a_rows), Arc::clone(&b_cols));
+            let (tx, rx) = oneshot::channel();
+            pool.spawn(Box::new(move || {
+                let cells = cells
+                    .map(|idx| {
+                        let (i, j) = (idx / cols, idx % cols);
+                        dot_product_slice(
+                            &a_rows[i * inner..(i + 1) * inner],
+                            &b_cols[j * inner..(j + 1) * inner],
+                        )
+                    })
+                    .collect::<Result<Vec<_>>>();
+                // the caller may already have returned, ignore send errors
+                let _ = tx.send(cells);
+            }));
+            rx
+        })
+        .collect::<Vec<_>>();
+
+    let mut data = Vec::with_capacity(total);
+    for rx in receivers {
+        let cells = rx
+            .recv()
+            .map_err(|_| anyhow!("Pool task was dropped before finishing"))??;
+        data.extend(cells);
+    }
+    Ok(Matrix {
+        rows: a.rows,
+        cols: b.cols,
+        data,
+    })
+}
+
+// fair split of `total` cells into `parts` contiguous ranges: the first `total % parts`
+// ranges get one cell more than the rest, so no range is empty while `total >= parts`
+fn cell_ranges(total: usize, parts: usize) -> Vec<Range<usize>> {
+    let (base, extra) = (total / parts, total % parts);
+    (0..parts)
+        .map(|w| {
+            let start = w * base + w.min(extra);
+            start..start + base + usize::from(w < extra)
+        })
+        .collect()
+}
+
+/// Thread count `multiply` uses for a `rows x inner` by `inner x cols` product: one
+/// thread for small problems, where spawning and messaging cost more than the
+/// arithmetic, scaling up with the work but never past `available_parallelism` or the
+/// number of output cells.
+pub fn recommended_threads(rows: usize, cols: usize, inner: usize) -> usize {
+    let work = rows.saturating_mul(cols).saturating_mul(inner);
+    let available = thread::available_parallelism().map_or(NUM_THREADS, |n| n.get());
+    (work / MIN_WORK_PER_THREAD)
+        .clamp(1, available)
+        .min(rows.saturating_mul(cols).max(1))
+}
+
+/// Options for the threaded `multiply`, e.g.
+/// `MultiplyConfig::new().threads(8).timeout(d).build().multiply(&a, &b)`.
+/// The default matches the plain `multiply` function.
+#[derive(Debug, Clone)]
+pub struct MultiplyConfig {
+    // None picks `recommended_threads` for each product
+    threads: Option<usize>,
+    chunked: bool,
+    timeout: Option<Duration>,
+    token: Option<CancellationToken>,
+}
+
+impl Default for MultiplyConfig {
+    fn default() -> Self {
+        Self {
+            threads: None,
+            chunked: true,
+            timeout: None,
+            token: None,
+        }
+    }
+}
+
+impl MultiplyConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // number of worker threads, must be at least 1; overrides `recommended_threads`
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = Some(threads);
+        self
+    }
+
+    // true (the default): each worker computes a contiguous range of output cells;
+    // false: cells are dealt out round-robin
+    pub fn chunked(mut self, chunked: bool) -> Self {
+        self.chunked = chunked;
+        self
+    }
+
+    // give up with a "timed out" error if the product isn't done within `timeout`
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    // stop early with a "cancelled" error once `token` is cancelled
+    pub fn cancellation(mut self, token: CancellationToken) -> Self {
+        self.token = Some(token);
+        self
+    }
+
+    pub fn build(self) -> Multiplier {
+        Multiplier { config: self }
+    }
+}
+
+// a configured multiply, built from `MultiplyConfig`
+#[derive(Debug, Clone)]
+pub struct Multiplier {
+    config: MultiplyConfig,
+}
+
+impl Multiplier {
+    pub fn multiply<T>(&self, a: &Matrix<T>, b: &Matrix<T>) -> Result<Matrix<T>>
+    where
+        T: Numeric,
+    {
+        self.multiply_with_stats(a, b).map(|(m, _)| m)
+    }
+
+    pub fn multiply_with_stats<T>(
+        &self,
+        a: &Matrix<T>,
+        b: &Matrix<T>,
+    ) -> Result<(Matrix<T>, Vec<WorkerStats>)>
+    where
+        T: Numeric,
+    {
+        self.run(a, b, Arc::new(dot_product::<T>))
+    }
+
+    // integer multiply with explicit overflow handling, see `Overflow`
+    pub fn multiply_with_policy<T>(
+        &self,
+        a: &Matrix<T>,
+        b: &Matrix<T>,
+        policy: Overflow,
+    ) -> Result<Matrix<T>>
+    where
+        T: Numeric + OverflowArith,
+    {
+        let kernel: Kernel<T> = match policy {
+            Overflow::Wrap => Arc::new(dot_product::<T>),
+            // both judge the exact cell value, not the partial sums on the way to it
+            Overflow::Saturate => Arc::new(|row, col| Ok(T::saturating_dot(&row, &col))),
+            Overflow::Error => Arc::new(|row, col| {
+                T::exact_dot(&row, &col).map_err(|_| anyhow!("integer overflow"))
+            }),
+        };
+        self.run(a, b, kernel).map(|(m, _)| m)
+    }
+
+    pub fn multiply_mod(
+        &self,
+        a: &Matrix<u64>,
+        b: &Matrix<u64>,
+        modulus: u64,
+    ) -> Result<Matrix<u64>> {
+        if modulus == 0 {
+            return Err(anyhow!("Modulus must be non-zero"));
+        }
+        // a product of two residues fits in u128, so reduce once per term
+        let m = modulus as u128;
+        let kernel: Kernel<u64> = Arc::new(move |row, col| {
+            let sum = row.iter().zip(col.iter()).fold(0u128, |acc, (&a, &b)| {
+                (acc + (a as u128 % m) * (b as u128 % m)) % m
+            });
+            Ok(sum as u64)
+        });
+        self.run(a, b, kernel).map(|(m, _)| m)
+    }
+
+    /// See `multiply_streaming`. The stream ends early, after fewer than `rows * cols`
+    /// cells, if the multiply is cancelled or times out.
+    pub fn multiply_streaming<T>(
+        &self,
+        a: &Matrix<T>,
+        b: &Matrix<T>,
+    ) -> Result<impl Iterator<Item = (usize, usize, T)>>
+    where
+        T: Numeric,
+    {
+        let deadline = self.config.timeout.map(|t| Instant::now() + t);
+        let abort = CancellationToken::new();
+        let (tx, rx) = mpsc::channel();
+        self.dispatch(a, b, Arc::new(dot_product::<T>), &abort, |_| {
+            Reply::Stream(tx.clone())
+        })?;
+        // only the workers' clones are left, so the stream ends once they are all done
+        drop(tx);
+
+        let cols = b.cols;
+        // a caller that stops reading early also stops the workers
+        let abort = AbortOnDrop(abort);
+        Ok(std::iter::from_fn(move || {
+            let output = match deadline {
+                Some(deadline) => {
+                    rx.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                }
+                None => rx.recv().map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+            };
+            match output.map(|o| (o.idx, o.value)) {
+                Ok((idx, Ok(value))) => Some((idx / cols, idx % cols, value)),
+                _ => {
+                    abort.0.cancel();
+                    None
+                }
+            }
+        }))
+    }
+
+    fn run<T>(
+        &self,
+        a: &Matrix<T>,
+        b: &Matrix<T>,
+        kernel: Kernel<T>,
+    ) -> Result<(Matrix<T>, Vec<WorkerStats>)>
+    where
+        T: Numeric,
+    {
+        let deadline = self.config.timeout.map(|t| Instant::now() + t);
+        // set when we give up (timeout) so the workers skip the remaining cells
+        let abort = CancellationToken::new();
+        let mut receivers = Vec::with_capacity(a.rows * b.cols);
+        let handles = self.dispatch(a, b, kernel, &abort, |_| {
+            let (tx, rx) = oneshot::channel();
+            receivers.push(rx);
+            Reply::Cell(tx)
+        })?;
+
+        let mut result = vec![T::default(); a.rows * b.cols];
+        for rx in receivers {
+            let output = match deadline {
+                Some(deadline) => rx.recv_deadline(deadline).map_err(|e| match e {
+                    oneshot::RecvTimeoutError::Timeout => {
+                        abort.cancel();
+                        anyhow!("Matrix multiplication timed out")
+                    }
+                    oneshot::RecvTimeoutError::Disconnected => self.worker_gone(),
+                }),
+                None => rx.recv().map_err(|_| self.worker_gone()),
+            }?;
+            result[output.idx] = output.value.map_err(|e| {
+                anyhow!(
+                    "Cell ({}, {}): {}",
+                    output.idx / b.cols,
+                    output.idx % b.cols,
+                    e
+                )
+            })?;
+        }
+
+        let stats = handles
+            .into_iter()
+            .map(|h| {
+                h.join()
+                    .map_err(|_| anyhow!("Matrix multiplication worker panicked"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok((Matrix::new(a.rows, b.cols, result), stats))
+    }
+
+    // validate, start the workers and queue every output cell, each answered through
+    // the `Reply` that `reply` makes for its index
+    fn dispatch<T>(
+        &self,
+        a: &Matrix<T>,
+        b: &Matrix<T>,
+        kernel: Kernel<T>,
+        abort: &CancellationToken,
+        mut reply: impl FnMut(usize) -> Reply<T>,
+    ) -> Result<Vec<thread::JoinHandle<WorkerStats>>>
+    where
+        T: Numeric,
+    {
+        let config = &self.config;
+        a.check_shape()?;
+        b.check_shape()?;
+        if a.cols != b.rows {
+            return Err(anyhow!("Incompatible matrix dimensions"));
+        }
+        let threads = config
+            .threads
+            .unwrap_or_else(|| recommended_threads(a.rows, b.cols, a.cols));
+        if threads == 0 {
+            return Err(anyhow!("Matrix multiplication needs at least one thread"));
+        }
+
+        let (senders, handles): (Vec<_>, Vec<_>) = (0..threads)
+            .map(|_| {
+                let (tx, rx) = mpsc::channel::<Msg<T>>();
+                let token = config.token.clone();
+                let abort = abort.clone();
+                let kernel = Arc::clone(&kernel);
+
+                let handle = thread::spawn(move || {
+                    let mut stats = WorkerStats::default();
+                    for msg in rx {
+                        // dropping the reply sender tells the receiving side we gave up
+                        if abort.is_cancelled() || token.as_ref().is_some_and(|t| t.is_cancelled())
+                        {
+                            continue;
+                        }
+                        let start = Instant::now();
+                        let value = kernel(msg.input.row, msg.input.col);
+                        stats.busy += start.elapsed();
+                        stats.cells += 1;
+                        msg.sender.send(MsgOutput {
+                            idx: msg.input.idx,
+                            value,
+                        });
+                    }
+                    stats
+                });
+                (tx, handle)
+            })
+            .unzip();
+
+        // chunked: each worker gets a contiguous range of output cells rather than every
+        // threads-th one, so neighbouring cells share rows on the same thread
+        let ranges = cell_ranges(a.rows * b.cols, threads);
+        let worker_for = |idx: usize| {
+            if config.chunked {
+                ranges.partition_point(|cells| cells.end <= idx)
+            } else {
+                idx % threads
+            }
+        };
+        // Every cell message owns its row and column, so those two copies per cell are
+        // unavoidable here (`multiply_direct` avoids them). Transposing b once turns each
+        // column copy into a contiguous memcpy into an exactly sized buffer, instead of a
+        // strided gather over b for every cell.
+        let bt = b.transpose();
+        for i in 0..a.rows {
+            let row_data = &a.data[i * a.cols..(i + 1) * a.cols];
+            for j in 0..b.cols {
+                let idx = i * b.cols + j;
+                let mut row = Vec::with_capacity(a.cols);
+                row.extend_from_slice(row_data);
+                let mut col = Vec::with_capacity(b.rows);
+                col.extend_from_slice(&bt.data[j * b.rows..(j + 1) * b.rows]);
+                let (row, col) = (Vector::new(row), Vector::new(col));
+                let input = MsgInput::new(idx, row, col);
+                senders[worker_for(idx)]
+                    .send(Msg::new(input, reply(idx)))
+                    .unwrap();
+            }
+        }
+        // closing the job channels lets the workers finish once they've drained them
+        drop(senders);
+
+        Ok(handles)
+    }
+
+    fn worker_gone(&self) -> anyhow::Error {
+        match &self.config.token {
+            Some(t) if t.is_cancelled() => anyhow!("Matrix multiplication cancelled"),
+            _ => anyhow!("Matrix multiplication worker exited unexpectedly"),
+        }
+    }
+}
+
+/// Like `multiply`, but each worker writes its contiguous range of cells straight into
+/// the output buffer instead of sending every cell back over its own channel, so no
+/// per-cell messages, receivers or column copies are allocated. Uses
+/// `recommended_threads` workers.
+pub fn multiply_direct<T>(a: &Matrix<T>, b: &Matrix<T>) -> Result<Matrix<T>>
+where
+    T: Copy + Default + FusedMulAdd + Send + Sync,
+{
+    a.check_shape()?;
+    b.check_shape()?;
+    if a.cols != b.rows {
+        return Err(anyhow!("Incompatible matrix dimensions"));
+    }
+
+    let mut result = vec![T::default(); a.rows * b.cols];
+    let ranges = cell_ranges(result.len(), recommended_threads(a.rows, b.cols, a.cols));
+    thread::scope(|s| {
+        let mut rest = result.as_mut_slice();
+        for range in ranges {
+            let (cells, tail) = rest.split_at_mut(range.len());
+            rest = tail;
+            s.spawn(move || {
+                for (idx, cell) in range.zip(cells) {
+                    let (i, j) = (idx / b.cols, idx % b.cols);
+                    let row = &a.data[i * a.cols..(i + 1) * a.cols];
+                    *cell = row.iter().enumerate().fold(T::default(), |acc, (k, &v)| {
+                        v.fused_mul_add(b.data[k * b.cols + j], acc)
+                    });
+                }
+            });
+        }
+    });
+
+    Ok(Matrix {
+        rows: a.rows,
+        cols: b.cols,
+        data: result,
+    })
+}
+
+/// `trace(a * b)` computed as `sum_i sum_k a[i][k] * b[k][i]`, in `O(n * m)` without
+/// forming the product. Needs `a` to be `n x m` and `b` to be `m x n`.
+pub fn trace_of_product<T: Numeric>(a: &Matrix<T>, b: &Matrix<T>) -> Result<T> {
+    a.check_shape()?;
+    b.check_shape()?;
+    if a.cols != b.rows || a.rows != b.cols {
+        return Err(anyhow!(
+            "trace(a * b) needs a {}x{} b, got {}x{}",
+            a.cols,
+            a.rows,
+            b.rows,
+            b.cols
+        ));
+    }
+    let mut sum = T::default();
+    for i in 0..a.rows {
+        for k in 0..a.cols {
+            sum = a.data[i * a.cols + k].fused_mul_add(b.data[k * b.cols + i], sum);
+        }
+    }
+    Ok(sum)
+}
+
+/// Coordinates, in row-major order, of the cells of `a * b` that are structurally
+/// non-zero: `(i, j)` such that some `k` has both `a[i][k]` and `b[k][j]` non-zero, where
+/// zero means `T::default()`. Values are never multiplied, so cancellation (e.g.
+/// `1 * 1 + 1 * -1`) still counts as non-zero. Useful to size the output of a sparse
+/// product before computing it.
+pub fn multiply_pattern<T>(a: &Matrix<T>, b: &Matrix<T>) -> Result<Vec<(usize, usize)>>
+where
+    T: Copy + Default + PartialEq,
+{
+    a.check_shape()?;
+    b.check_shape()?;
+    if a.cols != b.rows {
+        return Err(anyhow!("Incompatible matrix dimensions"));
+    }
+    let zero = T::default();
+    // non-zero columns of each row of b
+    let b_rows = (0..b.rows)
+        .map(|k| {
+            (0..b.cols)
+                .filter(|&j| b.data[k * b.cols + j] != zero)
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let mut pattern = Vec::new();
+    let mut hit = vec![false; b.cols];
+    for i in 0..a.rows {
+        hit.fill(false);
+        for k in (0..a.cols).filter(|&k| a.data[i * a.cols + k] != zero) {
+            for &j in &b_rows[k] {
+                hit[j] = true;
+            }
+        }
+        pattern.extend((0..b.cols).filter(|&j| hit[j]).map(|j| (i, j)));
+    }
+    Ok(pattern)
+}
+
+// a.len() x b.len() matrix of a[i] - b[j]; square it element-wise for squared distances
+pub fn outer_difference<T>(a: &Vector<T>, b: &Vector<T>) -> Matrix<T>
+where
+    T: Copy + Sub<Output = T>,
+{
+    Matrix::from_fn(a.len(), b.len(), |i, j| a[i] - b[j])
+}
+
+/// Freivalds' check that `a * b == c` in O(n^2) per trial: pick a random 0/1 vector `r`
+/// and compare `a * (b * r)` with `c * r`. A correct product always passes; a wrong one
+/// slips through a single trial with probability at most 1/2, so at most `2^-trials`
+/// overall. Mismatched dimensions fail. Needs exact arithmetic (`Eq`); floats round
+/// differently along the two paths, so use `verify_product_approx` for them.
+#[cfg(feature = "rand")]
+pub fn verify_product<T>(a: &Matrix<T>, b: &Matrix<T>, c: &Matrix<T>, trials: usize) -> bool
+where
+    T: Numeric + Eq + From<u8>,
+{
+    freivalds(a, b, c, trials, |abr, cr| abr == cr)
+}
+
+// `verify_product` for floats: `a * (b * r)` and `c * r` may differ by `epsilon` per element
+#[cfg(feature = "rand")]
+pub fn verify_product_approx(
+    a: &Matrix<f64>,
+    b: &Matrix<f64>,
+    c: &Matrix<f64>,
+    trials: usize,
+    epsilon: f64,
+) -> bool {
+    freivalds(a, b, c, trials, |abr, cr| {
+        abr.iter().zip(cr).all(|(x, y)| (x - y).abs() <= epsilon)
+    })
+}
+
+#[cfg(feature = "rand")]
+fn freivalds<T>(
+    a: &Matrix<T>,
+    b: &Matrix<T>,
+    c: &Matrix<T>,
+    trials: usize,
+    same: impl Fn(&[T], &[T]) -> bool,
+) -> bool
+where
+    T: Numeric + From<u8>,
+{
+    use rand::Rng;
+
+    if a.cols != b.rows || a.rows != c.rows || b.cols != c.cols {
+        return false;
+    }
+    let mut rng = rand::thread_rng();
+    (0..trials).all(|_| {
+        let r = Vector::new(
+            (0..b.cols)
+                .map(|_| T::from(rng.gen_range(0..=1)))
+                .collect::<Vec<_>>(),
+        );
+        let abr = b.mul_vector(&r).and_then(|br| a.mul_vector(&br));
+        match (abr, c.mul_vector(&r)) {
+            (Ok(abr), Ok(cr)) => same(&abr, &cr),
+            _ => false,
+        }
+    })
+}
+
+/// Multiply with every element widened to `W` (e.g. `i32` operands accumulated in
+/// `i64`), then narrow the product back to `T`. Fails with the coordinate of the first
+/// cell that doesn't fit in `T`, or whose wide accumulation already overflows `W`.
+pub fn multiply_narrowing<T, W>(a: &Matrix<T>, b: &Matrix<T>) -> Result<Matrix<T>>
+where
+    T: Copy + Into<W> + TryFrom<W>,
+    W: Numeric + OverflowArith,
+{
+    let wide = multiply_with_policy(
+        &a.map(|&v| v.into()),
+        &b.map(|&v| v.into()),
+        Overflow::Error,
+    )?;
+    let mut data = Vec::with_capacity(wide.data.len());
+    for (idx, &v) in wide.data.iter().enumerate() {
+        let v = T::try_from(v).map_err(|_| {
+            anyhow!(
+                "Cell ({}, {}) overflows the narrow type",
+                idx / wide.cols,
+                idx % wide.cols
+            )
+        })?;
+        data.push(v);
+    }
+    Ok(Matrix {
+        rows: wide.rows,
+        cols: wide.cols,
+        data,
+    })
+}
+
+/// Brackets and separators for `Matrix::format_with`. The default is the `Display` style
+/// `{1 2, 3 4}`; `numpy()` gives `[[1, 2], [3, 4]]` and `matlab()` gives `[1 2; 3 4]`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DisplayOptions {
+    pub open: &'static str,
+    pub close: &'static str,
+    pub row_open: &'static str,
+    pub row_close: &'static str,
+    pub element_separator: &'static str,
+    pub row_separator: &'static str,
+}
+
+impl Default for DisplayOptions {
+    fn default() -> Self {
+        Self {
+            open: "{",
+            close: "}",
+            row_open: "",
+            row_close: "",
+            element_separator: " ",
+            row_separator: ", ",
+        }
+    }
+}
+
+impl DisplayOptions {
+    pub fn numpy() -> Self {
+        Self {
+            open: "[",
+            close: "]",
+            row_open: "[",
+            row_close: "]",
+            element_separator: ", ",
+            row_separator: ", ",
+        }
+    }
+
+    pub fn matlab() -> Self {
+        Self {
+            open: "[",
+            close: "]",
+            row_open: "",
+            row_close: "",
+            element_separator: " ",
+            row_separator: "; ",
+        }
+    }
+}
+
+impl<T: fmt::Display> Matrix<T> {
+    pub fn format_with(&self, opts: DisplayOptions) -> String {
+        let mut out = String::new();
+        // writing into a String can't fail
+        let _ = self.write_with(&mut out, &opts);
+        out
+    }
+
+    fn write_with(&self, w: &mut impl fmt::Write, opts: &DisplayOptions) -> fmt::Result {
+        w.write_str(opts.open)?;
+        // an empty matrix has no cells, so no row separators either
+        if self.rows > 0 && self.cols > 0 {
+            for i in 0..self.rows {
+                if i > 0 {
+                    w.write_str(opts.row_separator)?;
+                }
+                w.write_str(opts.row_open)?;
+                for j in 0..self.cols {
+                    if j > 0 {
+                        w.write_str(opts.element_separator)?;
+                    }
+                    write!(w, "{}", self.data[i * self.cols + j])?;
+                }
+                w.write_str(opts.row_close)?;
+            }
+        }
+        w.write_str(opts.close)
+    }
+}
+
+impl<T> fmt::Display for Matrix<T>
+where
+    T: fmt::Display,
+{
+    // display a 2x3 as {1 2 3, 4 5 6}, 3x2 as {1 2, 3 4, 5 6}
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_with(f, &DisplayOptions::default())
+    }
+}
+
+impl<T> fmt::Debug for Matrix<T>
+where
+    T: fmt::Display,
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Matrix({}, {}, {})", self.rows, self.cols, self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AmapMetrics, StdSpawner, ThreadPool};
+    use proptest::prelude::*;
+    use std::num::Wrapping;
+
+    fn matrix(rows: usize, cols: usize) -> impl Strategy<Value = Matrix<i64>> {
+        prop::collection::vec(-100i64..100, rows * cols)
+            .prop_map(move |data| Matrix::new(rows, cols, data))
+    }
+
+    proptest! {
+        #[test]
+        fn prop_multiply_by_identity(
+            a in (1usize..8, 1usize..8).prop_flat_map(|(r, c)| matrix(r, c))
+        ) {
+            prop_assert_eq!(multiply(&a, &Matrix::identity(a.cols)).unwrap(), a);
+        }
+
+        #[test]
+        fn prop_multiply_is_associative(
+            (a, b, c) in (1usize..6, 1usize..6, 1usize..6, 1usize..6)
+                .prop_flat_map(|(m, n, p, q)| (matrix(m, n), matrix(n, p), matrix(p, q)))
+        ) {
+            let left = multiply(&multiply(&a, &b).unwrap(), &c).unwrap();
+            let right = multiply(&a, &multiply(&b, &c).unwrap()).unwrap();
+            prop_assert_eq!(left, right);
+        }
+
+        #[test]
+        fn prop_multiply_transpose(
+            (a, b) in (1usize..8, 1usize..8, 1usize..8)
+                .prop_flat_map(|(m, n, p)| (matrix(m, n), matrix(n, p)))
+        ) {
+            let left = multiply(&a, &b).unwrap().transpose();
+            let right = multiply(&b.transpose(), &a.transpose()).unwrap();
+            prop_assert_eq!(left, right);
+        }
+
+        #[test]
+        fn prop_policy_judges_exact_value(
+            (a, b) in (1usize..4, 1usize..6, 1usize..4).prop_flat_map(|(m, n, p)| (
+                prop::collection::vec(any::<i8>(), m * n).prop_map(move |d| Matrix::new(m, n, d)),
+                prop::collection::vec(any::<i8>(), n * p).prop_map(move |d| Matrix::new(n, p, d)),
+            ))
+        ) {
+            let exact = multiply(&a.cast::<i64>(), &b.cast::<i64>()).unwrap();
+            let saturated = multiply_with_policy(&a, &b, Overflow::Saturate).unwrap();
+            prop_assert_eq!(saturated, exact.map(|&v| v.clamp(-128, 127) as i8));
+            let fits = exact.data.iter().all(|&v| i8::try_from(v).is_ok());
+            prop_assert_eq!(multiply_with_policy(&a, &b, Overflow::Error).is_ok(), fits);
+        }
+
+        #[test]
+        fn prop_fma_multiply_close_to_naive(
+            (a, b) in (1usize..8, 1usize..8, 1usize..8)
+                .prop_flat_map(|(m, n, p)| (matrix(m, n), matrix(n, p)))
+        ) {
+            let a = a.cast_lossy::<f64>().map(|v| v / 7.0);
+            let b = b.cast_lossy::<f64>().map(|v| v / 3.0);
+            let naive = Matrix::from_fn(a.rows, b.cols, |i, j| {
+                (0..a.cols).fold(0.0, |acc, k| acc + a.data[i * a.cols + k] * b.data[k * b.cols + j])
+            });
+            let error = multiply(&a, &b).unwrap().max_abs_error(&naive).unwrap();
+            prop_assert!(error < 1e-9, "error {}", error);
+        }
+    }
+
+    #[test]
+    fn test_matrix_multiply() -> Result<()> {
+        let a = Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]);
+        let b = Matrix::new(3, 2, vec![1, 2, 3, 4, 5, 6]);
+        let c = a * b;
+        assert_eq!(c.rows, 2);
+        assert_eq!(c.cols, 2);
+        assert_eq!(c.data, vec![22, 28, 49, 64]);
+        assert_eq!(format!("{}", c), "{22 28, 49 64}");
+
+        let w = Matrix::new(1, 2, vec![Wrapping(i32::MAX), Wrapping(1)]);
+        let v = Matrix::new(2, 1, vec![Wrapping(2), Wrapping(2)]);
+        assert_eq!(multiply(&w, &v)?.data, vec![Wrapping(0)]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_multiply_uneven_partition() -> Result<()> {
+        // 3x3 = 9 cells over 4 workers: ranges of 3, 2, 2 and 2, nobody idle
+        let a = Matrix::new(3, 2, vec![1, 2, 3, 4, 5, 6]);
+        let b = Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]);
+        let multiplier = MultiplyConfig::new().threads(NUM_THREADS).build();
+        let (c, stats) = multiplier.multiply_with_stats(&a, &b)?;
+        assert_eq!(c.data, vec![9, 12, 15, 19, 26, 33, 29, 40, 51]);
+        assert!(stats.iter().all(|s| s.cells > 0));
+
+        // 5 cells: 2, 1, 1, 1
+        let a = Matrix::new(5, 1, vec![1, 2, 3, 4, 5]);
+        let (c, stats) = multiplier.multiply_with_stats(&a, &Matrix::new(1, 1, vec![2]))?;
+        assert_eq!(c.data, vec![2, 4, 6, 8, 10]);
+        let cells = stats.iter().map(|s| s.cells).collect::<Vec<_>>();
+        assert_eq!(cells, vec![2, 1, 1, 1]);
+
+        assert_eq!(cell_ranges(5, 4), vec![0..2, 2..3, 3..4, 4..5]);
+        assert_eq!(cell_ranges(2, 4), vec![0..1, 1..2, 2..2, 2..2]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_multiply_with_stats() -> Result<()> {
+        let a = Matrix::new(3, 2, vec![1, 2, 3, 4, 5, 6]);
+        let b = Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]);
+        let (c, stats) = MultiplyConfig::new()
+            .threads(NUM_THREADS)
+            .build()
+            .multiply_with_stats(&a, &b)?;
+        assert_eq!(c, multiply(&a, &b)?);
+        assert_eq!(stats.len(), NUM_THREADS);
+        assert_eq!(
+            stats.iter().map(|s| s.cells).collect::<Vec<_>>(),
+            vec![3, 2, 2, 2]
+        );
+
+        // without an explicit count a tiny product runs on a single worker
+        let (_, stats) = multiply_with_stats(&a, &b)?;
+        assert_eq!(stats.len(), 1);
+        assert_eq!(stats[0].cells, 9);
+        Ok(())
+    }
+
+    #[test]
+    fn test_recommended_threads() {
+        let available = thread::available_parallelism().map_or(NUM_THREADS, |n| n.get());
+        assert_eq!(recommended_threads(3, 3, 2), 1);
+        assert_eq!(recommended_threads(0, 0, 0), 1);
+        assert_eq!(recommended_threads(1000, 1000, 1000), available);
+        // never more workers than output cells
+        assert_eq!(recommended_threads(1, 1, usize::MAX), 1);
+        assert!(recommended_threads(64, 64, 64 * available) <= available);
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_verify_product() -> Result<()> {
+        let a = Matrix::from_fn(6, 5, |i, j| (i * 5 + j) as i64 - 10);
+        let b = Matrix::from_fn(5, 4, |i, j| (i * j) as i64 % 7);
+        let mut c = multiply(&a, &b)?;
+        assert!(verify_product(&a, &b, &c, 20));
+
+        c.data[13] += 1;
+        assert!(!verify_product(&a, &b, &c, 20));
+        assert!(!verify_product(&a, &a, &c, 20));
+
+        // rounding differs between a * (b * r) and c * r, hence the tolerance
+        let a = Matrix::random_seeded(20, 20, 1);
+        let b = Matrix::random_seeded(20, 20, 2);
+        let mut c = multiply(&a, &b)?;
+        assert!(verify_product_approx(&a, &b, &c, 20, 1e-9));
+        c.data[13] += 1e-3;
+        assert!(!verify_product_approx(&a, &b, &c, 20, 1e-9));
+        Ok(())
+    }
+
+    #[test]
+    fn test_trace_of_product() -> Result<()> {
+        let a = Matrix::from_fn(3, 4, |i, j| (i * 4 + j) as i64 - 5);
+        let b = Matrix::from_fn(4, 3, |i, j| (i + 2 * j) as i64 % 5);
+        assert_eq!(trace_of_product(&a, &b)?, multiply(&a, &b)?.trace()?);
+        assert_eq!(Matrix::new(2, 2, vec![1, 2, 3, 4]).trace()?, 5);
+        assert_eq!(
+            Matrix::new(2, 2, vec![i32::MAX, 0, 0, 1]).trace()?,
+            i32::MIN
+        );
+
+        assert!(trace_of_product(&a, &a).is_err());
+        assert!(a.trace().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_multiply_streaming() -> Result<()> {
+        let a = Matrix::from_fn(30, 20, |i, j| (i * 20 + j) as i64 % 9 - 4);
+        let b = Matrix::from_fn(20, 25, |i, j| (i * 3 + j) as i64 % 7 - 3);
+        let expected = multiply(&a, &b)?;
+
+        let mut cells = MultiplyConfig::new()
+            .threads(4)
+            .build()
+            .multiply_streaming(&a, &b)?
+            .collect::<Vec<_>>();
+        assert_eq!(cells.len(), 30 * 25);
+        cells.sort_unstable_by_key(|&(i, j, _)| (i, j));
+        assert_eq!(
+            cells.into_iter().map(|(_, _, v)| v).collect::<Vec<_>>(),
+            expected.data
+        );
+
+        // cancelled before the first cell: the stream ends without any output
+        let token = CancellationToken::new();
+        token.cancel();
+        let cancelled = MultiplyConfig::new().cancellation(token).build();
+        assert_eq!(cancelled.multiply_streaming(&a, &b)?.count(), 0);
+
+        assert!(multiply_streaming(&a, &a).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_multiply_in_pool() -> Result<()> {
+        let a = Matrix::from_fn(40, 30, |i, j| (i * 3 + j) as i64 % 7 - 3);
+        let b = Matrix::from_fn(30, 50, |i, j| (i + j * 5) as i64 % 11 - 5);
+        let expected = multiply(&a, &b)?;
+
+        assert_eq!(multiply_in_pool(&a, &b, &StdSpawner)?, expected);
+        let pool = ThreadPool::new(3)?;
+        assert_eq!(multiply_in_pool(&a, &b, &pool)?, expected);
+        #[cfg(feature = "rayon")]
+        {
+            let rayon = rayon::ThreadPoolBuilder::new().num_threads(2).build()?;
+            assert_eq!(multiply_in_pool(&a, &b, &rayon)?, expected);
+        }
+
+        // a spawner that never runs anything fails instead of hanging
+        struct Dropper;
+        impl Spawner for Dropper {
+            fn spawn(&self, _f: crate::Task) {}
+        }
+        assert!(multiply_in_pool(&a, &b, &Dropper).is_err());
+        assert!(multiply_in_pool(&a, &a, &StdSpawner).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_multiply_config() -> Result<()> {
+        let a = Matrix::new(3, 2, vec![1, 2, 3, 4, 5, 6]);
+        let b = Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]);
+        let expected = multiply(&a, &b)?;
+
+        let (c, stats) = MultiplyConfig::new()
+            .threads(2)
+            .chunked(false)
+            .timeout(Duration::from_secs(10))
+            .build()
+            .multiply_with_stats(&a, &b)?;
+        assert_eq!(c, expected);
+        assert_eq!(
+            stats.iter().map(|s| s.cells).collect::<Vec<_>>(),
+            vec![5, 4]
+        );
+
+        let single = MultiplyConfig::new().threads(1).build();
+        assert_eq!(single.multiply(&a, &b)?, expected);
+        assert!(MultiplyConfig::new()
+            .threads(0)
+            .build()
+            .multiply(&a, &b)
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_multiply_with_policy() -> Result<()> {
+        let a = Matrix::new(1, 2, vec![100i8, 100]);
+        let b = Matrix::new(2, 2, vec![1i8, -1, 1, 1]);
+        assert_eq!(multiply(&a, &b)?.data, vec![-56, 0]);
+        assert_eq!(
+            multiply_with_policy(&a, &b, Overflow::Wrap)?.data,
+            vec![-56, 0]
+        );
+        assert_eq!(
+            multiply_with_policy(&a, &b, Overflow::Saturate)?.data,
+            vec![127, 0]
+        );
+        let err = multiply_with_policy(&a, &b, Overflow::Error).unwrap_err();
+        assert_eq!(err.to_string(), "Cell (0, 0): integer overflow");
+
+        let c = Matrix::new(1, 2, vec![i8::MIN, 1]);
+        let d = Matrix::new(2, 1, vec![1i8, -1]);
+        assert_eq!(
+            multiply_with_policy(&c, &d, Overflow::Saturate)?.data,
+            vec![i8::MIN]
+        );
+        assert!(multiply_with_policy(&c, &d, Overflow::Error).is_err());
+
+        // 100 + 100 - 100 = 100 fits, even though the partial sum 200 doesn't
+        let e = Matrix::new(1, 3, vec![100i8, 100, -100]);
+        let f = Matrix::new(3, 1, vec![1i8, 1, 1]);
+        for policy in [Overflow::Wrap, Overflow::Saturate, Overflow::Error] {
+            assert_eq!(multiply_with_policy(&e, &f, policy)?.data, vec![100]);
+        }
+
+        // the products themselves overflow i128 and u128 here
+        let g = Matrix::new(1, 2, vec![i128::MAX, 1]);
+        let h = Matrix::new(2, 1, vec![2, -i128::MAX]);
+        assert_eq!(
+            multiply_with_policy(&g, &h, Overflow::Error)?.data,
+            vec![i128::MAX]
+        );
+        let u = Matrix::new(1, 1, vec![u128::MAX]);
+        assert_eq!(
+            multiply_with_policy(&u, &u, Overflow::Saturate)?.data,
+            vec![u128::MAX]
+        );
+        assert_eq!(
+            multiply_with_policy(&g, &g.transpose(), Overflow::Saturate)?.data,
+            vec![i128::MAX]
+        );
+        let n = Matrix::new(1, 2, vec![i128::MIN, i128::MIN]);
+        let p = Matrix::new(2, 1, vec![1, 1]);
+        assert_eq!(
+            multiply_with_policy(&n, &p, Overflow::Saturate)?.data,
+            vec![i128::MIN]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_multiply_config_timeout() {
+        // a single long dot product that can't be done by the time it's dispatched
+        let a = Matrix::new(1, 1_000_000, vec![1.0; 1_000_000]);
+        let b = Matrix::new(1_000_000, 1, vec![1.0; 1_000_000]);
+        let err = MultiplyConfig::new()
+            .timeout(Duration::ZERO)
+            .build()
+            .multiply(&a, &b)
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Matrix multiplication timed out");
+    }
+
+    #[test]
+    fn test_multiply_timed() -> Result<()> {
+        let metrics = AmapMetrics::new(&["matmul.elapsed_us", "matmul.count"]);
+        let a = Matrix::new(2, 2, vec![1, 2, 3, 4]);
+        let c = multiply_timed(&a, &a, &metrics, "matmul")?;
+        assert_eq!(c.data, vec![7, 10, 15, 22]);
+        assert!(multiply_timed(&a, &Matrix::new(1, 1, vec![1]), &metrics, "matmul").is_err());
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.get("matmul.count"), Some(&2));
+        assert!(snapshot["matmul.elapsed_us"] >= 0);
+        assert!(multiply_timed(&a, &a, &metrics, "unregistered").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_outer_difference() {
+        let a = Vector::new(vec![1, 5]);
+        let b = Vector::new(vec![2, -1]);
+        let d = outer_difference(&a, &b);
+        assert_eq!((d.rows, d.cols), (2, 2));
+        assert_eq!(d.data, vec![-1, 2, 3, 6]);
+        assert_eq!(d.map(|x| x * x).data, vec![1, 4, 9, 36]);
+    }
+
+    #[test]
+    fn test_multiply_pattern() -> Result<()> {
+        let a = Matrix::new(3, 2, vec![1, 0, 0, 0, 1, 1]);
+        let b = Matrix::new(2, 3, vec![0, 2, 0, 0, 0, -2]);
+        assert_eq!(multiply_pattern(&a, &b)?, vec![(0, 1), (2, 1), (2, 2)]);
+
+        // structural: the cancelling cell is still reported
+        let c = Matrix::new(1, 2, vec![1, 1]);
+        let d = Matrix::new(2, 1, vec![1, -1]);
+        assert_eq!(multiply(&c, &d)?.data, vec![0]);
+        assert_eq!(multiply_pattern(&c, &d)?, vec![(0, 0)]);
+
+        assert!(multiply_pattern(&a, &a).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_multiply_rejects_malformed_matrix() {
+        // only reachable through `new` in a release build
+        let a = Matrix {
+            rows: 2,
+            cols: 2,
+            data: vec![1, 2, 3],
+        };
+        let b = Matrix::new(2, 2, vec![1, 2, 3, 4]);
+        let err = multiply(&a, &b).unwrap_err();
+        assert_eq!(err.to_string(), "Matrix declared as 2x2 holds 3 elements");
+        assert!(multiply(&b, &a).is_err());
+    }
+
+    #[test]
+    fn test_matrix_try_new() {
+        assert_eq!(
+            Matrix::try_new(2, 2, vec![1, 2, 3, 4]).unwrap(),
+            Matrix::new(2, 2, vec![1, 2, 3, 4])
+        );
+        let err = Matrix::try_new(2, 2, vec![1, 2, 3]).unwrap_err();
+        assert_eq!(err.to_string(), "Matrix declared as 2x2 holds 3 elements");
+        assert!(Matrix::<i32>::try_new(usize::MAX, 2, vec![]).is_err());
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "Matrix declared as 2x2 holds 3 elements")]
+    fn test_matrix_new_checks_shape_in_debug() {
+        Matrix::new(2, 2, vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_multiply_direct() -> Result<()> {
+        let a = Matrix::new(3, 2, vec![1, 2, 3, 4, 5, 6]);
+        let b = Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(multiply_direct(&a, &b)?, multiply(&a, &b)?);
+        assert!(multiply_direct(&a, &a).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_multiply_cancellable() -> Result<()> {
+        let a = Matrix::new(2, 2, vec![1, 2, 3, 4]);
+        let b = Matrix::new(2, 2, vec![1, 2, 3, 4]);
+
+        let token = CancellationToken::new();
+        assert_eq!(
+            multiply_cancellable(&a, &b, &token)?.data,
+            vec![7, 10, 15, 22]
+        );
+
+        token.cancel();
+        let err = multiply_cancellable(&a, &b, &token).unwrap_err();
+        assert_eq!(err.to_string(), "Matrix multiplication cancelled");
+        Ok(())
+    }
+
+    #[test]
+    fn test_multiply_narrowing() -> Result<()> {
+        let big = i32::MAX / 2 + 1;
+        let a = Matrix::new(2, 2, vec![big, big, 1, 0]);
+        let b = Matrix::new(2, 2, vec![1, 1, 1, -1]);
+        // row 0: big + big overflows i32 in column 0 but cancels out in column 1
+        let err = multiply_narrowing::<i32, i64>(&a, &b).unwrap_err();
+        assert_eq!(err.to_string(), "Cell (0, 0) overflows the narrow type");
+
+        let a = Matrix::new(1, 2, vec![big, big]);
+        let b = Matrix::new(2, 1, vec![2, -2]);
+        assert_eq!(multiply_narrowing::<i32, i64>(&a, &b)?.data, vec![0]);
+
+        // 4 * 2^62 overflows the i64 accumulator itself and must not wrap to 0
+        let a = Matrix::new(1, 4, vec![i32::MIN; 4]);
+        let b = Matrix::new(4, 1, vec![i32::MIN; 4]);
+        let err = multiply_narrowing::<i32, i64>(&a, &b).unwrap_err();
+        assert_eq!(err.to_string(), "Cell (0, 0): integer overflow");
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_display() -> Result<()> {
+        let a = Matrix::new(2, 2, vec![1, 2, 3, 4]);
+        let b = Matrix::new(2, 2, vec![1, 2, 3, 4]);
+        let c = multiply(&a, &b)?;
+        assert_eq!(c.data, vec![7, 10, 15, 22]);
+        assert_eq!(format!("{}", c), "{7 10, 15 22}");
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_format_with() {
+        let m = Matrix::new(2, 2, vec![1, 2, 3, 4]);
+        assert_eq!(m.format_with(DisplayOptions::default()), m.to_string());
+        assert_eq!(m.format_with(DisplayOptions::numpy()), "[[1, 2], [3, 4]]");
+        assert_eq!(m.format_with(DisplayOptions::matlab()), "[1 2; 3 4]");
+        let csv = DisplayOptions {
+            open: "",
+            close: "\n",
+            element_separator: ",",
+            row_separator: "\n",
+            ..DisplayOptions::default()
+        };
+        assert_eq!(m.format_with(csv), "1,2\n3,4\n");
+        assert_eq!(
+            Matrix::new(0, 0, Vec::<i32>::new()).format_with(DisplayOptions::numpy()),
+            "[]"
+        );
+    }
+
+    #[test]
+    fn test_matrix_display_empty() {
+        assert_eq!(Matrix::new(0, 0, Vec::<i32>::new()).to_string(), "{}");
+        assert_eq!(Matrix::new(2, 0, Vec::<i32>::new()).to_string(), "{}");
+        assert_eq!(
+            format!("{:?}", Matrix::new(0, 3, Vec::<i32>::new())),
+            "Matrix(0, 3, {})"
+        );
+    }
+
+    #[test]
+    fn test_matrix_split_rows() -> Result<()> {
+        let a = Matrix::new(5, 2, (1..=10).collect::<Vec<_>>());
+        let blocks = a.split_rows(2);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(
+            (blocks[0].rows, blocks[0].data.clone()),
+            (2, vec![1, 2, 3, 4])
+        );
+        assert_eq!(
+            (blocks[1].rows, blocks[1].data.clone()),
+            (3, vec![5, 6, 7, 8, 9, 10])
+        );
+
+        let blocks = a.split_rows(8);
+        assert_eq!(blocks.len(), 5);
+        assert!(blocks.iter().all(|b| b.rows == 1 && b.cols == 2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_transpose() -> Result<()> {
+        let a = Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]);
+        let t = a.transpose();
+        assert_eq!((t.rows, t.cols), (3, 2));
+        assert_eq!(t.data, vec![1, 4, 2, 5, 3, 6]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_assemble_row_blocks() -> Result<()> {
+        let a = Matrix::new(5, 2, (1..=10).collect::<Vec<_>>());
+        let b = Matrix::new(2, 2, vec![1, 0, 0, 1]);
+        let blocks = a
+            .split_rows(3)
+            .iter()
+            .map(|block| multiply(block, &b))
+            .collect::<Result<Vec<_>>>()?;
+        assert_eq!(Matrix::assemble_row_blocks(blocks, 2)?, a);
+
+        let ragged = vec![
+            Matrix::new(1, 2, vec![1, 2]),
+            Matrix::new(1, 3, vec![1, 2, 3]),
+        ];
+        let err = Matrix::assemble_row_blocks(ragged, 2).unwrap_err();
+        assert_eq!(err.to_string(), "Block 1 has 3 columns, expected 2");
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_first_asymmetry() {
+        let a = Matrix::new(3, 3, vec![1, 2, 3, 2, 5, 6, 3, 6, 9]);
+        assert!(a.is_symmetric());
+        assert_eq!(a.first_asymmetry(), None);
+
+        let b = Matrix::new(3, 3, vec![1, 2, 3, 2, 5, 6, 3, 7, 9]);
+        assert!(!b.is_symmetric());
+        assert_eq!(b.first_asymmetry(), Some((1, 2)));
+
+        let c = Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(c.first_asymmetry(), None);
+        assert!(!c.is_symmetric());
+    }
+
+    #[test]
+    fn test_matrix_block_diag() {
+        let a = Matrix::new(2, 2, vec![1, 2, 3, 4]);
+        let b = Matrix::new(2, 2, vec![5, 6, 7, 8]);
+        let d = Matrix::block_diag(&[a, b]);
+        assert_eq!((d.rows, d.cols), (4, 4));
+        assert_eq!(d.data, vec![1, 2, 0, 0, 3, 4, 0, 0, 0, 0, 5, 6, 0, 0, 7, 8]);
+        assert_eq!(Matrix::<i32>::block_diag(&[]).data, vec![]);
+    }
+
+    #[test]
+    fn test_matrix_from_rows() -> Result<()> {
+        let rows = [Vector::new(vec![1, 2, 3]), Vector::new(vec![4, 5, 6])];
+        let a = Matrix::from_rows(&rows)?;
+        assert_eq!((a.rows, a.cols), (2, 3));
+        assert_eq!(a.data, vec![1, 2, 3, 4, 5, 6]);
+
+        let ragged = [Vector::new(vec![1, 2, 3]), Vector::new(vec![4, 5])];
+        assert!(Matrix::from_rows(&ragged).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_try_from_row_iter() -> Result<()> {
+        let lines = ["1 2 3", "4 5 6"];
+        let rows = lines.iter().map(|line| {
+            line.split_whitespace()
+                .map(|v| v.parse::<i32>().unwrap())
+                .collect::<Vec<_>>()
+        });
+        let a = Matrix::try_from_row_iter(3, rows)?;
+        assert_eq!((a.rows, a.cols), (2, 3));
+        assert_eq!(a.data, vec![1, 2, 3, 4, 5, 6]);
+
+        let err = Matrix::try_from_row_iter(2, vec![vec![1, 2], vec![3, 4], vec![5]]).unwrap_err();
+        assert_eq!(err.to_string(), "Row 2 has length 1, expected 2");
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_copy_col_into() -> Result<()> {
+        let a = Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]);
+        let mut buf = vec![9; 10];
+        a.copy_col_into(1, &mut buf)?;
+        assert_eq!(buf, vec![2, 5]);
+        a.copy_col_into(2, &mut buf)?;
+        assert_eq!(buf, vec![3, 6]);
+        assert!(a.copy_col_into(3, &mut buf).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_row_view() -> Result<()> {
+        let a = Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]);
+        let row = a.row_view(1)?;
+        assert_eq!(&*row, &[4, 5, 6]);
+        assert_eq!(dot_product_slice(&row, &a.row_view(0)?)?, 32);
+        assert!(a.row_view(2).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_expect_shape() {
+        let m = Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(m.expect_shape(2, 3).diagonal(), vec![1, 5]);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "expected a 3x2 matrix, got 2x3 holding 6 elements")]
+    fn test_matrix_expect_shape_panics() {
+        Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]).expect_shape(3, 2);
+    }
+
+    #[test]
+    fn test_matrix_map_in_place() {
+        let mut m = Matrix::new(2, 2, vec![1, -2, 3, 4]);
+        m.map_in_place(|x| *x *= 2);
+        assert_eq!(m.data, vec![2, -4, 6, 8]);
+        m.map_in_place(|x| *x = (*x).max(0));
+        assert_eq!(m.data, vec![2, 0, 6, 8]);
+    }
+
+    #[test]
+    fn test_matrix_try_map() {
+        let a = Matrix::new(1, 3, vec!["1", "2", "3"]);
+        let parsed = a.try_map(|s| s.parse::<i32>()).unwrap();
+        assert_eq!(parsed.data, vec![1, 2, 3]);
+
+        let b = Matrix::new(1, 3, vec!["1", "x", "3"]);
+        assert!(b.try_map(|s| s.parse::<i32>()).is_err());
+    }
+
+    #[test]
+    fn test_matrix_cast() {
+        let a = Matrix::new(1, 3, vec![1, -2, 300]);
+        assert_eq!(a.cast::<f64>().data, vec![1.0, -2.0, 300.0]);
+        assert_eq!(a.cast::<i64>().data, vec![1, -2, 300]);
+        assert_eq!(a.cast_lossy::<u8>().data, vec![1, 254, 44]);
+
+        let b = Matrix::new(1, 3, vec![1.9, -2.5, 1e10]);
+        assert_eq!(b.cast_lossy::<i32>().data, vec![1, -2, i32::MAX]);
+    }
+
+    #[test]
+    fn test_matrix_iter_colmajor() {
+        let a = Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]);
+        let items = a.iter_colmajor().copied().collect::<Vec<_>>();
+        assert_eq!(items, vec![1, 4, 2, 5, 3, 6]);
+    }
+
+    #[test]
+    fn test_matrix_from_fn() {
+        let a = Matrix::from_fn(2, 3, |i, j| i * 10 + j);
+        assert_eq!(a.data, vec![0, 1, 2, 10, 11, 12]);
+    }
+
+    #[cfg(feature = "rand")]
+    #[test]
+    fn test_matrix_random_seeded() {
+        let a = Matrix::random_seeded(3, 4, 42);
+        let b = Matrix::random_seeded(3, 4, 42);
+        let c = Matrix::random_seeded(3, 4, 7);
+        assert_eq!(a.data, b.data);
+        assert_ne!(a.data, c.data);
+        assert!(a.data.iter().all(|v| (0.0..1.0).contains(v)));
+    }
+
+    #[test]
+    fn test_matrix_mul_vector() -> Result<()> {
+        let a = Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]);
+        let v = a.mul_vector(&Vector::new(vec![1, 0, -1]))?;
+        assert_eq!(*v, vec![-2, -2]);
+        assert!(a.mul_vector(&Vector::new(vec![1, 2])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_steady_state() -> Result<()> {
+        let p = Matrix::new(2, 2, vec![0.9, 0.1, 0.5, 0.5]);
+        let pi = p.steady_state(1000, 1e-12)?;
+        assert!((pi[0] - 5.0 / 6.0).abs() < 1e-9);
+        assert!((pi[1] - 1.0 / 6.0).abs() < 1e-9);
+
+        let not_stochastic = Matrix::new(2, 2, vec![0.9, 0.2, 0.5, 0.5]);
+        assert!(not_stochastic.steady_state(1000, 1e-12).is_err());
+
+        // period 2: the uniform start oscillates and never settles
+        let periodic = Matrix::new(3, 3, vec![0.0, 1.0, 0.0, 0.5, 0.0, 0.5, 0.0, 1.0, 0.0]);
+        assert!(periodic.steady_state(1000, 1e-12).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_determinant() -> Result<()> {
+        assert_eq!(
+            Matrix::new(2, 2, vec![3, 8, 4, 6]).determinant_small()?,
+            -14
+        );
+        let a = Matrix::new(3, 3, vec![6, 1, 1, 4, -2, 5, 2, 8, 7]);
+        assert_eq!(a.determinant_small()?, -306);
+        assert!(Matrix::new(2, 3, vec![0; 6]).determinant_small().is_err());
+        assert!(Matrix::<i32>::identity(4).determinant_small().is_err());
+
+        let b = Matrix::new(3, 3, vec![6.0, 1.0, 1.0, 4.0, -2.0, 5.0, 2.0, 8.0, 7.0]);
+        assert_eq!(b.determinant()?, -306.0);
+        let c = Matrix::new(
+            4,
+            4,
+            vec![
+                1.0, 0.0, 2.0, -1.0, 3.0, 0.0, 0.0, 5.0, 2.0, 1.0, 4.0, -3.0, 1.0, 0.0, 5.0, 0.0,
+            ],
+        );
+        assert!((c.determinant()? - 30.0).abs() < 1e-9);
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_standardize_columns() -> Result<()> {
+        let a = Matrix::new(4, 2, vec![1.0, 3.0, 2.0, 3.0, 3.0, 3.0, 4.0, 3.0]);
+        let z = a.standardize_columns()?;
+        let scale = 1.25_f64.sqrt();
+        let expected = Matrix::new(
+            4,
+            2,
+            vec![
+                -1.5 / scale,
+                0.0,
+                -0.5 / scale,
+                0.0,
+                0.5 / scale,
+                0.0,
+                1.5 / scale,
+                0.0,
+            ],
+        );
+        assert!(z.approx_eq(&expected, 1e-12));
+        assert!(Matrix::new(0, 2, Vec::<f64>::new())
+            .standardize_columns()
+            .is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_div_scalar_and_reciprocal() -> Result<()> {
+        let a = Matrix::new(2, 2, vec![1.0, 2.0, 4.0, -8.0]);
+        assert_eq!(a.div_scalar(2.0)?.data, vec![0.5, 1.0, 2.0, -4.0]);
+        assert_eq!(a.reciprocal()?.data, vec![1.0, 0.5, 0.25, -0.125]);
+
+        assert!(a.div_scalar(0.0).is_err());
+        let err = Matrix::new(2, 2, vec![1.0, 2.0, 0.0, 4.0])
+            .reciprocal()
+            .unwrap_err();
+        assert_eq!(err.to_string(), "Reciprocal of zero at (1, 0)");
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_is_orthogonal() {
+        let (s, c) = (0.6_f64, 0.8_f64);
+        let rotation = Matrix::new(2, 2, vec![c, -s, s, c]);
+        assert!(rotation.is_orthogonal(1e-12));
+
+        let tall = Matrix::new(3, 2, vec![1.0, 0.0, 0.0, 0.0, 0.0, 1.0]);
+        assert!(tall.is_orthogonal(1e-12));
+
+        let skewed = Matrix::new(2, 2, vec![1.0, 0.1, 0.0, 1.0]);
+        assert!(!skewed.is_orthogonal(1e-6));
+    }
+
+    #[test]
+    fn test_matrix_pow_elements() {
+        let a = Matrix::new(2, 2, vec![1i64, -2, 3, 0]);
+        assert_eq!(a.pow_elements(3).data, vec![1, -8, 27, 0]);
+        assert_eq!(a.pow_elements(0).data, vec![1, 1, 1, 1]);
+        // element-wise, not the matrix power a * a
+        assert_ne!(a.pow_elements(2), multiply(&a, &a).unwrap());
+
+        let f = Matrix::new(1, 3, vec![4.0, 9.0, 0.25]);
+        assert_eq!(f.powf_elements(0.5).data, vec![2.0, 3.0, 0.5]);
+    }
+
+    #[test]
+    fn test_matrix_scaled_identity() {
+        let m = Matrix::scaled_identity(3, 5);
+        assert_eq!(m.to_string(), "{5 0 0, 0 5 0, 0 0 5}");
+        assert_eq!(Matrix::<i32>::eye(2), Matrix::identity(2));
+        assert_eq!(Matrix::<f64>::scaled_identity(0, 1.0).data, vec![]);
+    }
+
+    #[test]
+    fn test_matrix_kronecker() -> Result<()> {
+        let a = Matrix::new(2, 2, vec![1, 2, 3, 4]);
+        let b = Matrix::new(1, 2, vec![0, 5]);
+        assert_eq!(a.kronecker(&b).to_string(), "{0 5 0 10, 0 15 0 20}");
+
+        // [[1, 2], [3, 4]] ⊕ [[5, 6], [7, 8]] written out block by block
+        let c = Matrix::new(2, 2, vec![5, 6, 7, 8]);
+        let sum = a.kronecker_sum(&c)?;
+        assert_eq!(sum.to_string(), "{6 6 2 0, 7 9 0 2, 3 0 9 6, 0 3 7 12}");
+
+        assert!(a.kronecker_sum(&b).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_duplicate_rows() {
+        let m = Matrix::new(5, 2, vec![1, 2, 3, 4, 1, 2, 5, 6, 1, 2]);
+        let expected = vec![(0, 2), (0, 4), (2, 4)];
+        assert_eq!(m.duplicate_rows(), expected);
+        assert_eq!(m.duplicate_rows_hashed(), expected);
+
+        let unique = Matrix::new(2, 2, vec![1, 2, 2, 1]);
+        assert!(unique.duplicate_rows().is_empty());
+        assert!(unique.duplicate_rows_hashed().is_empty());
+    }
+
+    #[test]
+    fn test_matrix_triangular() {
+        let m = Matrix::new(3, 3, (1..=9).collect::<Vec<_>>());
+        assert_eq!(
+            m.upper_triangular(true).to_string(),
+            "{1 2 3, 0 5 6, 0 0 9}"
+        );
+        assert_eq!(
+            m.upper_triangular(false).to_string(),
+            "{0 2 3, 0 0 6, 0 0 0}"
+        );
+        assert_eq!(
+            m.lower_triangular(true).to_string(),
+            "{1 0 0, 4 5 0, 7 8 9}"
+        );
+        assert_eq!(
+            m.lower_triangular(false).to_string(),
+            "{0 0 0, 4 0 0, 7 8 0}"
+        );
+    }
+
+    #[test]
+    fn test_matrix_scale_rows_cols() -> Result<()> {
+        let m = Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]);
+        let rows = Vector::new(vec![10, -1]);
+        let cols = Vector::new(vec![1, 0, 2]);
+        assert_eq!(m.scale_rows(&rows)?.to_string(), "{10 20 30, -4 -5 -6}");
+        assert_eq!(m.scale_cols(&cols)?.to_string(), "{1 0 6, 4 0 12}");
+
+        // same as multiplying by the diagonal matrix
+        let diag = Matrix::from_fn(2, 2, |i, j| if i == j { rows[i] } else { 0 });
+        assert_eq!(m.scale_rows(&rows)?, multiply(&diag, &m)?);
+
+        assert!(m.scale_rows(&cols).is_err());
+        assert!(m.scale_cols(&rows).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_diagonal_at() {
+        let m = Matrix::new(3, 4, (1..=12).collect::<Vec<_>>());
+        assert_eq!(m.diagonal(), vec![1, 6, 11]);
+        assert_eq!(m.diagonal_at(1), vec![2, 7, 12]);
+        assert_eq!(m.diagonal_at(3), vec![4]);
+        assert_eq!(m.diagonal_at(-1), vec![5, 10]);
+        assert_eq!(m.diagonal_at(-2), vec![9]);
+        assert!(m.diagonal_at(4).is_empty());
+        assert!(m.diagonal_at(-3).is_empty());
+    }
+
+    #[test]
+    fn test_matrix_argmax() {
+        let scores = Matrix::new(3, 3, vec![0.1, 0.7, 0.2, 0.9, 0.05, 0.05, 0.3, 0.3, 0.4]);
+        assert_eq!(*scores.argmax_rows(), vec![1, 0, 2]);
+        assert_eq!(*scores.argmax_cols(), vec![1, 0, 2]);
+
+        let ties = Matrix::new(2, 2, vec![5, 5, 1, 2]);
+        assert_eq!(*ties.argmax_rows(), vec![0, 1]);
+        assert_eq!(*ties.argmax_cols(), vec![0, 0]);
+    }
+
+    #[test]
+    fn test_multiply_mod() -> Result<()> {
+        let a = Matrix::new(2, 2, vec![1, 2, 3, 4]);
+        let b = Matrix::new(2, 2, vec![5, 6, 7, 8]);
+        // [[19, 22], [43, 50]] mod 7
+        assert_eq!(multiply_mod(&a, &b, 7)?.data, vec![5, 1, 1, 1]);
+
+        // the plain product would overflow u64
+        let big = Matrix::new(1, 2, vec![u64::MAX, u64::MAX]);
+        let col = Matrix::new(2, 1, vec![u64::MAX, u64::MAX]);
+        let p = 1_000_000_007;
+        let r = u64::MAX % p;
+        assert_eq!(
+            multiply_mod(&big, &col, p)?.data,
+            vec![((2 * (r as u128) * r as u128) % p as u128) as u64]
+        );
+
+        assert!(multiply_mod(&a, &b, 0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_add() -> Result<()> {
+        let a = Matrix::new(2, 2, vec![1, 2, 3, 4]);
+        assert_eq!(a.add(&a)?.data, vec![2, 4, 6, 8]);
+        assert!(a.add(&Matrix::new(1, 2, vec![1, 2])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_symmetrize() -> Result<()> {
+        let cov = Matrix::new(2, 2, vec![2.0, 0.5 + 1e-12, 0.5, 1.0]);
+        assert!(!cov.is_symmetric());
+        let sym = cov.symmetrize()?;
+        assert!(sym.is_symmetric());
+        assert!(sym.approx_eq(&cov, 1e-9));
+        assert_eq!(sym.data[0], 2.0);
+
+        assert!(Matrix::new(1, 2, vec![1.0, 2.0]).symmetrize().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_threshold() {
+        let m = Matrix::new(2, 3, vec![0.05, -0.2, 0.1, -0.1, 3.0, -0.0999]);
+        assert_eq!(m.threshold(0.1).data, vec![0.0, -0.2, 0.1, -0.1, 3.0, 0.0]);
+        assert_eq!(m.threshold(0.0), m);
+    }
+
+    #[test]
+    fn test_matrix_histogram() {
+        let m = Matrix::new(2, 4, vec![0.0, 0.1, 0.5, 0.99, 1.0, -3.0, 7.0, f64::NAN]);
+        assert_eq!(m.histogram(4, 0.0, 1.0), vec![4, 0, 1, 3]);
+        assert_eq!(m.histogram(1, 0.0, 1.0), vec![8]);
+        assert!(m.histogram(0, 0.0, 1.0).is_empty());
+
+        // enough elements to be split across several threads
+        let big = Matrix::from_fn(600, 600, |i, j| ((i * 600 + j) % 100) as f64);
+        let counts = big.histogram(10, 0.0, 100.0);
+        assert_eq!(counts.iter().sum::<usize>(), 600 * 600);
+        assert!(counts.iter().all(|&c| c == 600 * 600 / 10));
+    }
+
+    #[test]
+    fn test_matrix_compose() -> Result<()> {
+        let scale = Matrix::new(2, 2, vec![2.0, 0.0, 0.0, 3.0]);
+        // 90 degrees counter-clockwise
+        let rotate = Matrix::new(2, 2, vec![0.0, -1.0, 1.0, 0.0]);
+        let scale_then_rotate = rotate.compose(&scale)?;
+
+        let v = Vector::new(vec![1.0, 1.0]);
+        let stepwise = rotate.mul_vector(&scale.mul_vector(&v)?)?;
+        assert_eq!(*scale_then_rotate.mul_vector(&v)?, *stepwise);
+        assert_eq!(*stepwise, vec![-3.0, 2.0]);
+
+        let project = Matrix::new(1, 3, vec![1.0, 0.0, 0.0]);
+        assert!(rotate.compose(&project).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_pairwise_distances() -> Result<()> {
+        let points = Matrix::new(3, 2, vec![0.0, 0.0, 3.0, 4.0, 6.0, 8.0]);
+        let d = points.pairwise_distances()?;
+        assert_eq!(d.to_string(), "{0 5 10, 5 0 5, 10 5 0}");
+
+        let big = Matrix::from_fn(120, 40, |i, j| ((i * 7 + j * 3) % 11) as f64);
+        let d = big.pairwise_distances()?;
+        assert!(d.is_symmetric());
+        assert!((0..120).all(|i| d.data[i * 120 + i] == 0.0));
+        let expected = euclidean_distance(&big.row_view(17)?, &big.row_view(90)?)?;
+        assert_eq!(d.data[17 * 120 + 90], expected);
+
+        assert_eq!(
+            Matrix::new(0, 2, Vec::<f64>::new())
+                .pairwise_distances()?
+                .data,
+            vec![]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_l2_normalize_rows() -> Result<()> {
+        let m = Matrix::new(3, 2, vec![3.0, 4.0, 0.0, 0.0, 1.0, 1.0]);
+        let n = m.l2_normalize_rows()?;
+        assert_eq!(&*n.row_view(0)?, &[0.6, 0.8]);
+        assert_eq!(&*n.row_view(1)?, &[0.0, 0.0]);
+        assert!((Vector::new(n.row_view(2)?.to_vec()).norm() - 1.0).abs() < 1e-12);
+
+        // cosine similarity of the original rows is the dot product of the normalized ones
+        let (a, b) = (m.row_view(0)?, m.row_view(2)?);
+        let cosine = dot_product_slice(&a, &b)?
+            / (Vector::new(a.to_vec()).norm() * Vector::new(b.to_vec()).norm());
+        let dot = dot_product_slice(&n.row_view(0)?, &n.row_view(2)?)?;
+        assert!((cosine - dot).abs() < 1e-12);
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_spectral_radius() -> Result<()> {
+        // eigenvalues 5 and 2
+        let m = Matrix::new(2, 2, vec![4.0, 1.0, 2.0, 3.0]);
+        assert!((m.spectral_radius(200)? - 5.0).abs() < 1e-9);
+
+        // the dominant eigenvalue is negative, the radius is its magnitude
+        let d = Matrix::new(3, 3, vec![-7.0, 0.0, 0.0, 0.0, 2.0, 0.0, 0.0, 0.0, 1.0]);
+        assert!((d.spectral_radius(500)? - 7.0).abs() < 1e-9);
+
+        assert_eq!(Matrix::new(2, 2, vec![0.0; 4]).spectral_radius(10)?, 0.0);
+        assert!(Matrix::new(1, 2, vec![1.0, 2.0])
+            .spectral_radius(10)
+            .is_err());
+        assert!(m.spectral_radius(0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_max_abs_error() -> Result<()> {
+        let a = Matrix::new(2, 2, vec![1.0, 2.0, 3.0, 4.0]);
+        let b = Matrix::new(2, 2, vec![1.5, 2.0, 2.0, 4.25]);
+        assert_eq!(a.max_abs_error(&b)?, 1.0);
+        assert_eq!(a.max_abs_error(&a)?, 0.0);
+        let nan = Matrix::new(2, 2, vec![f64::NAN, 2.0, 3.0, 4.0]);
+        assert!(a.max_abs_error(&nan)?.is_nan());
+        assert!(a.max_abs_error(&Matrix::new(1, 2, vec![1.0, 2.0])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_stochastic() {
+        let chain = Matrix::new(2, 2, vec![0.9, 0.1, 0.5, 0.5]);
+        assert!(chain.is_row_stochastic(1e-12));
+        assert!(!chain.is_doubly_stochastic(1e-12));
+
+        let doubly = Matrix::new(3, 3, vec![0.2, 0.3, 0.5, 0.5, 0.2, 0.3, 0.3, 0.5, 0.2]);
+        assert!(doubly.is_row_stochastic(1e-12));
+        assert!(doubly.is_doubly_stochastic(1e-12));
+
+        let perturbed = Matrix::new(2, 2, vec![0.5, 0.5 + 1e-6, 0.5, 0.5]);
+        assert!(!perturbed.is_row_stochastic(1e-9));
+        assert!(perturbed.is_row_stochastic(1e-5));
+
+        // sums are fine but a negative entry isn't a probability
+        let negative = Matrix::new(2, 2, vec![1.5, -0.5, -0.5, 1.5]);
+        assert!(!negative.is_row_stochastic(1e-9));
+        assert!(!negative.is_doubly_stochastic(1e-9));
+    }
+
+    #[test]
+    fn test_matrix_row_col_sums() -> Result<()> {
+        let m = Matrix::new(2, 3, vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(*m.row_sums(), vec![6, 15]);
+        assert_eq!(*m.col_sums(), vec![5, 7, 9]);
+        assert!(Matrix::new(2, 0, Vec::<i32>::new()).col_sums().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_sinkhorn() -> Result<()> {
+        let m = Matrix::new(3, 3, vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 1.0]);
+        let d = m.sinkhorn(1000, 1e-12)?;
+        assert!(d.is_doubly_stochastic(1e-9));
+        // a diagonal scaling keeps the zero pattern and the cross ratios
+        let ratio = |m: &Matrix<f64>| (m.data[0] * m.data[4]) / (m.data[1] * m.data[3]);
+        assert!((ratio(&d) - ratio(&m)).abs() < 1e-9);
+
+        assert!(Matrix::new(2, 2, vec![1.0, -1.0, 1.0, 1.0])
+            .sinkhorn(10, 1e-9)
+            .is_err());
+        assert!(Matrix::new(2, 2, vec![0.0, 0.0, 1.0, 1.0])
+            .sinkhorn(10, 1e-9)
+            .is_err());
+        assert!(Matrix::new(1, 2, vec![1.0, 1.0])
+            .sinkhorn(10, 1e-9)
+            .is_err());
+        // no doubly stochastic matrix has this zero pattern
+        let stuck = Matrix::new(2, 2, vec![1.0, 1.0, 0.0, 1.0]);
+        assert!(stuck.sinkhorn(50, 1e-12).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_inverse() -> Result<()> {
+        let m = Matrix::new(3, 3, vec![0.0, 2.0, 1.0, 1.0, 1.0, 0.0, 3.0, 0.0, 2.0]);
+        let inv = m.inverse()?;
+        assert!(multiply(&m, &inv)?.approx_eq(&Matrix::identity(3), 1e-12));
+        assert!(multiply(&inv, &m)?.approx_eq(&Matrix::identity(3), 1e-12));
+
+        let singular = Matrix::new(2, 2, vec![1.0, 2.0, 2.0, 4.0]);
+        assert!(singular.inverse().is_err());
+        assert!(Matrix::new(1, 2, vec![1.0, 2.0]).inverse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_pseudoinverse() -> Result<()> {
+        let tall = Matrix::new(3, 2, vec![1.0, 2.0, 3.0, 4.0, 5.0, 7.0]);
+        let wide = tall.transpose();
+        for a in [&tall, &wide] {
+            let pinv = a.pseudoinverse()?;
+            assert_eq!((pinv.rows, pinv.cols), (a.cols, a.rows));
+            let back = multiply(&multiply(a, &pinv)?, a)?;
+            assert!(back.approx_eq(a, 1e-9));
+        }
+
+        let square = Matrix::new(2, 2, vec![2.0, 1.0, 1.0, 3.0]);
+        assert!(square.pseudoinverse()?.approx_eq(&square.inverse()?, 1e-12));
+
+        let rank_one = Matrix::new(3, 2, vec![1.0, 2.0, 2.0, 4.0, 3.0, 6.0]);
+        assert!(rank_one.pseudoinverse().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_lstsq() -> Result<()> {
+        // fit y = c + m * x to points scattered around y = 1 + 2x
+        let xs = [0.0, 1.0, 2.0, 3.0, 4.0];
+        let ys = Vector::new(vec![1.1, 2.9, 5.2, 6.8, 9.1]);
+        let a = Matrix::from_fn(xs.len(), 2, |i, j| if j == 0 { 1.0 } else { xs[i] });
+        let fit = a.lstsq(&ys)?;
+        assert!(fit.approx_eq(&Vector::new(vec![1.04, 1.99]), 1e-9));
+
+        // the residual is orthogonal to the columns, so any other x does worse
+        let residual =
+            |x: &Vector<f64>| -> Result<f64> { euclidean_distance(&a.mul_vector(x)?, &ys) };
+        let best = residual(&fit)?;
+        for delta in [[0.01, 0.0], [0.0, -0.01], [-0.02, 0.01]] {
+            let other = Vector::new(vec![fit[0] + delta[0], fit[1] + delta[1]]);
+            assert!(residual(&other)? > best);
+        }
+
+        assert!(a.lstsq(&Vector::new(vec![1.0, 2.0])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_max_norm_column() -> Result<()> {
+        let m = Matrix::new(2, 3, vec![1.0, 3.0, -1.0, 1.0, -4.0, 1.0]);
+        assert_eq!(m.max_norm_column()?, (1, 5.0));
+
+        let empty = Matrix::new(2, 0, Vec::<f64>::new());
+        assert!(empty.max_norm_column().is_err());
+        Ok(())
+    }
+
+    #[cfg(feature = "num-complex")]
+    #[test]
+    fn test_complex_matrix_multiply() -> Result<()> {
+        let c = |re, im| Complex::new(re, im);
+        let a = Matrix::new(
+            2,
+            2,
+            vec![c(1.0, 1.0), c(2.0, 0.0), c(0.0, 0.0), c(0.0, 1.0)],
+        );
+        let b = Matrix::new(
+            2,
+            2,
+            vec![c(1.0, 0.0), c(0.0, 1.0), c(1.0, -1.0), c(2.0, 0.0)],
+        );
+        let m = multiply(&a, &b)?;
+        assert_eq!(
+            m.data,
+            vec![c(3.0, -1.0), c(3.0, 1.0), c(1.0, 1.0), c(0.0, 2.0)]
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "num-complex")]
+    #[test]
+    fn test_complex_conjugate() -> Result<()> {
+        let c = |re, im| Complex::new(re, im);
+        let a = Matrix::new(
+            2,
+            2,
+            vec![c(1.0, 1.0), c(2.0, 0.0), c(0.0, 0.0), c(0.0, 1.0)],
+        );
+        assert_eq!(a.conjugate().conjugate().data, a.data);
+
+        let h = a.hermitian_transpose();
+        assert_eq!((h.rows, h.cols), (2, 2));
+        assert_eq!(
+            h.data,
+            vec![c(1.0, -1.0), c(0.0, 0.0), c(2.0, 0.0), c(0.0, -1.0)]
+        );
+
+        let v = Vector::new(vec![c(1.0, 2.0), c(-3.0, 0.5)]);
+        assert_eq!(*v.conjugate().conjugate(), *v);
         Ok(())
     }
 }
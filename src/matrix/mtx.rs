@@ -0,0 +1,283 @@
+// Matrix Market (.mtx) exchange format, see https://math.nist.gov/MatrixMarket/formats.html
+//
+// Only real-valued matrices are supported: the `real`, `double` and `integer` fields with
+// `general`, `symmetric` or `skew-symmetric` storage, in both the sparse `coordinate`
+// and the dense column-major `array` layout.
+
+use super::Matrix;
+use anyhow::{anyhow, Result};
+use std::fmt::Write;
+
+const BANNER: &str = "%%MatrixMarket";
+// largest element count we're willing to allocate for, whatever the size line claims
+const MAX_ELEMENTS: usize = 1 << 28;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Symmetry {
+    General,
+    Symmetric,
+    SkewSymmetric,
+}
+
+impl Matrix<f64> {
+    /// Parse a real matrix in Matrix Market format. The size line must match the data:
+    /// too few or too many entries, or a coordinate outside the declared dimensions, is
+    /// an error.
+    pub fn from_matrix_market(input: &str) -> Result<Matrix<f64>> {
+        let mut lines = input.lines().enumerate().map(|(n, line)| (n + 1, line));
+        let (_, header) = lines
+            .next()
+            .ok_or_else(|| anyhow!("Empty Matrix Market input"))?;
+        let (coordinate, symmetry) = parse_header(header)?;
+
+        // comments and blank lines may only precede the size line, but skipping them
+        // everywhere is harmless and lets hand-edited files through
+        let mut lines = lines.filter(|(_, line)| {
+            let line = line.trim();
+            !line.is_empty() && !line.starts_with('%')
+        });
+        let (n, size) = lines
+            .next()
+            .ok_or_else(|| anyhow!("Missing Matrix Market size line"))?;
+        let size = parse_fields::<usize>(n, size)?;
+
+        let (rows, cols, nnz) = match (coordinate, size.as_slice()) {
+            (true, &[rows, cols, nnz]) => (rows, cols, Some(nnz)),
+            (false, &[rows, cols]) => (rows, cols, None),
+            _ => return Err(anyhow!("Line {}: malformed size line", n)),
+        };
+        if symmetry != Symmetry::General && rows != cols {
+            return Err(anyhow!("Line {}: a symmetric matrix must be square", n));
+        }
+        // the sizes are untrusted: refuse to overflow or allocate absurd amounts
+        let len = rows
+            .checked_mul(cols)
+            .filter(|&len| len <= MAX_ELEMENTS)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Line {}: a {}x{} matrix exceeds {} elements",
+                    n,
+                    rows,
+                    cols,
+                    MAX_ELEMENTS
+                )
+            })?;
+        // can't overflow now that rows * cols fits in MAX_ELEMENTS
+        let entries = nnz.unwrap_or(match symmetry {
+            Symmetry::General => len,
+            Symmetry::Symmetric => cols * (cols + 1) / 2,
+            Symmetry::SkewSymmetric => cols * cols.saturating_sub(1) / 2,
+        });
+
+        let mut data = vec![0.0; len];
+        let mut set = |i: usize, j: usize, v: f64| {
+            data[i * cols + j] = v;
+            match symmetry {
+                Symmetry::General => {}
+                Symmetry::Symmetric => data[j * cols + i] = v,
+                Symmetry::SkewSymmetric => data[j * cols + i] = -v,
+            }
+        };
+
+        // array entries run down each column, only over the stored (lower) triangle
+        let mut positions = (0..cols).flat_map(|j| {
+            let first = match symmetry {
+                Symmetry::General => 0,
+                Symmetry::Symmetric => j,
+                Symmetry::SkewSymmetric => j + 1,
+            };
+            (first..rows).map(move |i| (i, j))
+        });
+
+        let mut read = 0;
+        for (n, line) in lines {
+            if read == entries {
+                return Err(anyhow!(
+                    "Line {}: more entries than the declared {}",
+                    n,
+                    entries
+                ));
+            }
+            if coordinate {
+                let fields = line.split_whitespace().collect::<Vec<_>>();
+                let &[i, j, v] = fields.as_slice() else {
+                    return Err(anyhow!("Line {}: expected `row col value`", n));
+                };
+                let (i, j) = (parse_index(n, i, rows)?, parse_index(n, j, cols)?);
+                if symmetry != Symmetry::General && j > i {
+                    return Err(anyhow!("Line {}: entry above the diagonal", n));
+                }
+                // the diagonal of a skew-symmetric matrix is zero and never stored
+                if symmetry == Symmetry::SkewSymmetric && j == i {
+                    return Err(anyhow!(
+                        "Line {}: diagonal entry in a skew-symmetric matrix",
+                        n
+                    ));
+                }
+                set(i, j, parse_value(n, v)?);
+            } else {
+                let [v] = parse_fields::<f64>(n, line)?[..] else {
+                    return Err(anyhow!("Line {}: expected a single value", n));
+                };
+                let (i, j) = positions
+                    .next()
+                    .ok_or_else(|| anyhow!("Line {}: more entries than the matrix holds", n))?;
+                set(i, j, v);
+            }
+            read += 1;
+        }
+        if read != entries {
+            return Err(anyhow!("Expected {} entries, found {}", entries, read));
+        }
+
+        Ok(Matrix { rows, cols, data })
+    }
+
+    // dense `array real general` form, values in column-major order
+    pub fn to_matrix_market(&self) -> String {
+        let mut out = format!("{} matrix array real general\n", BANNER);
+        let _ = writeln!(out, "{} {}", self.rows, self.cols);
+        for v in self.iter_colmajor() {
+            let _ = writeln!(out, "{}", v);
+        }
+        out
+    }
+
+    // sparse `coordinate real general` form listing only the non-zero entries
+    pub fn to_matrix_market_coordinate(&self) -> String {
+        let entries = self
+            .data
+            .iter()
+            .enumerate()
+            .filter(|(_, &v)| v != 0.0)
+            .map(|(idx, v)| (idx / self.cols + 1, idx % self.cols + 1, v))
+            .collect::<Vec<_>>();
+        let mut out = format!("{} matrix coordinate real general\n", BANNER);
+        let _ = writeln!(out, "{} {} {}", self.rows, self.cols, entries.len());
+        for (i, j, v) in entries {
+            let _ = writeln!(out, "{} {} {}", i, j, v);
+        }
+        out
+    }
+}
+
+// `%%MatrixMarket matrix <coordinate|array> <field> <symmetry>`, case-insensitive
+fn parse_header(header: &str) -> Result<(bool, Symmetry)> {
+    let fields = header
+        .split_whitespace()
+        .map(str::to_ascii_lowercase)
+        .collect::<Vec<_>>();
+    let fields = fields.iter().map(String::as_str).collect::<Vec<_>>();
+    let &[banner, "matrix", format, field, symmetry] = fields.as_slice() else {
+        return Err(anyhow!("Line 1: malformed Matrix Market header"));
+    };
+    if banner != BANNER.to_ascii_lowercase() {
+        return Err(anyhow!("Line 1: missing {} banner", BANNER));
+    }
+    let coordinate = match format {
+        "coordinate" => true,
+        "array" => false,
+        _ => return Err(anyhow!("Unsupported Matrix Market format `{}`", format)),
+    };
+    if !matches!(field, "real" | "double" | "integer") {
+        return Err(anyhow!("Unsupported Matrix Market field `{}`", field));
+    }
+    let symmetry = match symmetry {
+        "general" => Symmetry::General,
+        "symmetric" => Symmetry::Symmetric,
+        "skew-symmetric" => Symmetry::SkewSymmetric,
+        _ => return Err(anyhow!("Unsupported Matrix Market symmetry `{}`", symmetry)),
+    };
+    Ok((coordinate, symmetry))
+}
+
+fn parse_fields<T: std::str::FromStr>(n: usize, line: &str) -> Result<Vec<T>> {
+    line.split_whitespace()
+        .map(|f| {
+            f.parse()
+                .map_err(|_| anyhow!("Line {}: invalid number `{}`", n, f))
+        })
+        .collect()
+}
+
+fn parse_value(n: usize, field: &str) -> Result<f64> {
+    field
+        .parse()
+        .map_err(|_| anyhow!("Line {}: invalid number `{}`", n, field))
+}
+
+// 1-based index in the file, 0-based in the matrix
+fn parse_index(n: usize, field: &str, bound: usize) -> Result<usize> {
+    match field.parse::<usize>() {
+        Ok(idx) if (1..=bound).contains(&idx) => Ok(idx - 1),
+        _ => Err(anyhow!(
+            "Line {}: index `{}` outside 1..={}",
+            n,
+            field,
+            bound
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matrix_market_roundtrip() -> Result<()> {
+        let m = Matrix::new(2, 3, vec![1.0, 0.0, -2.5, 0.0, 4.0, 0.0]);
+        let array = m.to_matrix_market();
+        assert_eq!(
+            array,
+            "%%MatrixMarket matrix array real general\n2 3\n1\n0\n0\n4\n-2.5\n0\n"
+        );
+        assert_eq!(Matrix::from_matrix_market(&array)?, m);
+
+        let coordinate = m.to_matrix_market_coordinate();
+        assert_eq!(
+            coordinate,
+            "%%MatrixMarket matrix coordinate real general\n2 3 3\n1 1 1\n1 3 -2.5\n2 2 4\n"
+        );
+        assert_eq!(Matrix::from_matrix_market(&coordinate)?, m);
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_market_symmetric() -> Result<()> {
+        let input = "%%MatrixMarket matrix coordinate real symmetric\n\
+                     % lower triangle only\n\
+                     3 3 3\n1 1 2\n3 1 -1\n2 2 5\n";
+        let m = Matrix::from_matrix_market(input)?;
+        assert_eq!(m.to_string(), "{2 0 -1, 0 5 0, -1 0 0}");
+
+        let array = "%%MatrixMarket matrix array real skew-symmetric\n2 2\n3\n";
+        assert_eq!(
+            Matrix::from_matrix_market(array)?.to_string(),
+            "{0 -3, 3 0}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_matrix_market_errors() {
+        let cases = [
+            "",
+            "%%MatrixMarket matrix coordinate complex general\n1 1 1\n1 1 1 0\n",
+            "%%MatrixMarket matrix coordinate real general\n2 2 2\n1 1 1\n",
+            "%%MatrixMarket matrix coordinate real general\n2 2 1\n1 1 1\n2 2 1\n",
+            "%%MatrixMarket matrix coordinate real general\n2 2 1\n3 1 1\n",
+            "%%MatrixMarket matrix array real general\n2 2\n1\n2\n3\n",
+            "%%MatrixMarket matrix array real general\n1 1\n1\n2\n",
+            "%%MatrixMarket matrix coordinate real symmetric\n2 2 1\n1 2 1\n",
+            "%%MatrixMarket matrix coordinate real skew-symmetric\n2 2 1\n1 1 5\n",
+            "%%MatrixMarket matrix array real symmetric\n2 3\n",
+            "matrix array real general\n1 1\n1\n",
+            "%%MatrixMarket matrix coordinate real general\n4294967296 4294967296 0\n",
+            "%%MatrixMarket matrix array real symmetric\n4294967296 4294967296\n",
+            "%%MatrixMarket matrix array real general\n100000 100000\n",
+        ];
+        for input in cases {
+            assert!(Matrix::from_matrix_market(input).is_err(), "{:?}", input);
+        }
+    }
+}
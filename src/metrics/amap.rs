@@ -2,10 +2,12 @@ use anyhow::Result;
 use core::fmt;
 use std::{
     collections::HashMap,
+    io::{self, Write},
     sync::{
         atomic::{AtomicI64, Ordering},
         Arc,
     },
+    time::{Duration, SystemTime},
 };
 
 // Atomic map for metrics
@@ -16,24 +18,80 @@ pub struct AmapMetrics {
 
 impl AmapMetrics {
     pub fn new(metrics_names: &[&'static str]) -> Self {
-        let map = metrics_names
-            .iter()
-            .map(|&name| (name, AtomicI64::new(0)))
-            .collect();
-        Self {
-            data: Arc::new(map),
-        }
+        metrics_names.iter().copied().collect()
     }
 
     pub fn inc(&self, key: impl AsRef<str>) -> Result<()> {
+        self.add(key, 1)
+    }
+
+    pub fn add(&self, key: impl AsRef<str>, value: i64) -> Result<()> {
         let key = key.as_ref();
         let counter = self
             .data
             .get(key)
             .ok_or_else(|| anyhow::anyhow!("key {} not found", key))?;
-        counter.fetch_add(1, Ordering::Relaxed);
+        counter.fetch_add(value, Ordering::Relaxed);
         Ok(())
     }
+
+    pub fn get(&self, key: impl AsRef<str>) -> Option<i64> {
+        self.data
+            .get(key.as_ref())
+            .map(|v| v.load(Ordering::Relaxed))
+    }
+
+    /// Per-second rate of `key` since the caller read `prev_value`, `elapsed` ago. The
+    /// caller keeps the previous value, so the map holds no extra state. An unknown key
+    /// counts as 0; a zero `elapsed` yields 0.0.
+    pub fn rate_since(&self, key: impl AsRef<str>, prev_value: i64, elapsed: Duration) -> f64 {
+        super::rate(self.get(key).unwrap_or(0), prev_value, elapsed)
+    }
+
+    pub fn snapshot(&self) -> HashMap<String, i64> {
+        self.data
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.load(Ordering::Relaxed)))
+            .collect()
+    }
+
+    // the counters paired with the wall-clock time they were read, for time-series stores
+    pub fn snapshot_with_timestamp(&self) -> (SystemTime, HashMap<String, i64>) {
+        (SystemTime::now(), self.snapshot())
+    }
+
+    // entries ordered by key, for deterministic rendering
+    pub fn sorted_snapshot(&self) -> Vec<(&'static str, i64)> {
+        let mut entries = self
+            .data
+            .iter()
+            .map(|(&k, v)| (k, v.load(Ordering::Relaxed)))
+            .collect::<Vec<_>>();
+        entries.sort_unstable_by_key(|&(k, _)| k);
+        entries
+    }
+
+    // stream the counters in Prometheus text format, sorted by key
+    pub fn write_prometheus<W: Write>(&self, w: &mut W, prefix: &str) -> io::Result<()> {
+        super::write_prometheus(w, prefix, self.sorted_snapshot())
+    }
+
+    pub fn to_prometheus(&self, prefix: &str) -> io::Result<String> {
+        super::to_prometheus(|buf| self.write_prometheus(buf, prefix))
+    }
+}
+
+// build the key set from a computed list of names; duplicates collapse into one counter
+impl FromIterator<&'static str> for AmapMetrics {
+    fn from_iter<I: IntoIterator<Item = &'static str>>(iter: I) -> Self {
+        let map = iter
+            .into_iter()
+            .map(|name| (name, AtomicI64::new(0)))
+            .collect();
+        Self {
+            data: Arc::new(map),
+        }
+    }
 }
 
 impl Clone for AmapMetrics {
@@ -47,9 +105,82 @@ impl Clone for AmapMetrics {
 impl fmt::Display for AmapMetrics {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let data = self
-            .data
-            .iter()
-            .map(|(k, v)| format!("{}: {}", k, v.load(Ordering::Relaxed)));
+            .sorted_snapshot()
+            .into_iter()
+            .map(|(k, v)| format!("{}: {}", k, v));
         write!(f, "{}", data.collect::<Vec<String>>().join(", "))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_amap_from_iter() -> Result<()> {
+        let metrics = (1..=3)
+            .map(|i| ["req.page.1", "req.page.2", "req.page.3"][i - 1])
+            .chain(["req.page.1"])
+            .collect::<AmapMetrics>();
+        metrics.inc("req.page.1")?;
+        metrics.inc("req.page.3")?;
+
+        let snapshot = metrics.snapshot();
+        assert_eq!(snapshot.len(), 3);
+        assert_eq!(snapshot.get("req.page.1"), Some(&1));
+        assert_eq!(snapshot.get("req.page.2"), Some(&0));
+        assert!(metrics.inc("req.page.4").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_amap_sorted_snapshot() -> Result<()> {
+        let metrics = AmapMetrics::new(&["b", "c", "a"]);
+        metrics.inc("c")?;
+        metrics.inc("c")?;
+        metrics.inc("a")?;
+        assert_eq!(
+            metrics.sorted_snapshot(),
+            vec![("a", 1), ("b", 0), ("c", 2)]
+        );
+        assert_eq!(metrics.to_string(), "a: 1, b: 0, c: 2");
+        Ok(())
+    }
+
+    #[test]
+    fn test_amap_snapshot_with_timestamp() -> Result<()> {
+        let metrics = AmapMetrics::new(&["a"]);
+        metrics.inc("a")?;
+        let before = SystemTime::now();
+        let (at, snapshot) = metrics.snapshot_with_timestamp();
+        assert!(at >= before && at <= SystemTime::now());
+        assert_eq!(snapshot, metrics.snapshot());
+        Ok(())
+    }
+
+    #[test]
+    fn test_amap_rate_since() -> Result<()> {
+        let metrics = AmapMetrics::new(&["req"]);
+        let prev = metrics.get("req").unwrap();
+        metrics.add("req", 50)?;
+        assert_eq!(
+            metrics.rate_since("req", prev, Duration::from_millis(500)),
+            100.0
+        );
+        assert_eq!(metrics.rate_since("req", prev, Duration::ZERO), 0.0);
+        assert_eq!(metrics.get("missing"), None);
+        Ok(())
+    }
+
+    #[test]
+    fn test_amap_prometheus() -> Result<()> {
+        let metrics = AmapMetrics::new(&["req.page.2", "req.page.1"]);
+        metrics.inc("req.page.1")?;
+        assert_eq!(
+            metrics.to_prometheus("app")?,
+            "# TYPE app_req_page_1 counter\napp_req_page_1 1\n\
+             # TYPE app_req_page_2 counter\napp_req_page_2 0\n"
+        );
+        Ok(())
+    }
+}
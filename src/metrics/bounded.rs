@@ -0,0 +1,118 @@
+use super::Metrics;
+use anyhow::Result;
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+};
+
+// Like CmapMetrics, but holds at most `max_keys` keys: inserting a new key into a full
+// map evicts the least recently updated one, and that key's count is lost. Meant for
+// metrics keyed by unbounded external input such as URL paths.
+#[derive(Debug, Clone)]
+pub struct BoundedCmapMetrics {
+    max_keys: usize,
+    data: Arc<Mutex<BoundedMap>>,
+}
+
+#[derive(Debug, Default)]
+struct BoundedMap {
+    // key -> (count, tick of the last update)
+    counters: HashMap<String, (i64, u64)>,
+    // tick of the last update -> key, oldest first; ticks are unique
+    by_tick: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+impl BoundedCmapMetrics {
+    // `max_keys` of 0 is treated as 1
+    pub fn new(max_keys: usize) -> Self {
+        Self {
+            max_keys: max_keys.max(1),
+            data: Arc::new(Mutex::new(BoundedMap::default())),
+        }
+    }
+
+    pub fn inc(&self, key: impl Into<String>) -> Result<()> {
+        self.add(key, 1)
+    }
+
+    pub fn add(&self, key: impl Into<String>, value: i64) -> Result<()> {
+        let key = key.into();
+        let mut data = self.lock();
+        data.tick += 1;
+        let tick = data.tick;
+
+        let data = &mut *data;
+        match data.counters.get_mut(&key) {
+            Some((count, last)) => {
+                data.by_tick.remove(last);
+                *count += value;
+                *last = tick;
+            }
+            None => {
+                if data.counters.len() >= self.max_keys {
+                    if let Some((_, oldest)) = data.by_tick.pop_first() {
+                        data.counters.remove(&oldest);
+                    }
+                }
+                data.counters.insert(key.clone(), (value, tick));
+            }
+        }
+        data.by_tick.insert(tick, key);
+        Ok(())
+    }
+
+    pub fn get(&self, key: impl AsRef<str>) -> Option<i64> {
+        self.lock().counters.get(key.as_ref()).map(|&(v, _)| v)
+    }
+
+    pub fn snapshot(&self) -> Result<HashMap<String, i64>> {
+        Ok(self
+            .lock()
+            .counters
+            .iter()
+            .map(|(k, &(v, _))| (k.clone(), v))
+            .collect())
+    }
+
+    // same poison recovery as CmapMetrics
+    fn lock(&self) -> MutexGuard<'_, BoundedMap> {
+        self.data.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Metrics for BoundedCmapMetrics {
+    fn add(&self, key: &str, value: i64) -> Result<()> {
+        BoundedCmapMetrics::add(self, key, value)
+    }
+
+    fn snapshot(&self) -> Result<HashMap<String, i64>> {
+        BoundedCmapMetrics::snapshot(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bounded_evicts_least_recently_updated() -> Result<()> {
+        let metrics = BoundedCmapMetrics::new(2);
+        metrics.inc("/a")?;
+        metrics.inc("/b")?;
+        metrics.inc("/a")?;
+        // /b is the least recently updated key
+        metrics.inc("/c")?;
+
+        let snapshot = metrics.snapshot()?;
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(metrics.get("/a"), Some(2));
+        assert_eq!(metrics.get("/b"), None);
+        assert_eq!(metrics.get("/c"), Some(1));
+
+        metrics.inc("/d")?;
+        assert_eq!(metrics.get("/a"), None);
+        assert_eq!(metrics.lock().by_tick.len(), 2);
+        Ok(())
+    }
+}
@@ -0,0 +1,140 @@
+use super::Metrics;
+use anyhow::Result;
+use std::collections::HashMap;
+
+const FLUSH_EVERY: usize = 1024;
+
+// Per-thread front for a shared metrics backend: increments land in a private map and
+// are merged into the backend every `flush_every` increments, on `flush`, and on drop.
+// Each thread owns its own BufferedMetrics, so hot paths touch no atomics or locks,
+// at the price of the backend lagging behind by up to `flush_every` increments.
+// Unknown keys (for AmapMetrics) only surface as an error at flush time. Drop flushes
+// too but has nowhere to report a failure, so call `flush` explicitly to see errors.
+pub struct BufferedMetrics<M: Metrics> {
+    backing: M,
+    buffer: HashMap<String, i64>,
+    pending: usize,
+    flush_every: usize,
+}
+
+impl<M: Metrics> BufferedMetrics<M> {
+    pub fn new(backing: M) -> Self {
+        Self::with_flush_every(backing, FLUSH_EVERY)
+    }
+
+    // `flush_every` of 0 is treated as 1, i.e. no buffering
+    pub fn with_flush_every(backing: M, flush_every: usize) -> Self {
+        Self {
+            backing,
+            buffer: HashMap::new(),
+            pending: 0,
+            flush_every: flush_every.max(1),
+        }
+    }
+
+    // The increment is always taken: an error only reports counts for other keys that an
+    // automatic flush had to drop, so retrying would count twice.
+    pub fn inc(&mut self, key: &str) -> Result<()> {
+        match self.buffer.get_mut(key) {
+            Some(counter) => *counter += 1,
+            None => {
+                self.buffer.insert(key.to_string(), 1);
+            }
+        }
+        self.pending += 1;
+        if self.pending >= self.flush_every {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    // tries every key and empties the buffer; counts the backend rejects are dropped
+    // after being reported once, as the first error
+    pub fn flush(&mut self) -> Result<()> {
+        let mut first_err = None;
+        for (key, value) in self.buffer.drain() {
+            if let Err(e) = self.backing.add(&key, value) {
+                first_err.get_or_insert(e);
+            }
+        }
+        self.pending = 0;
+        first_err.map_or(Ok(()), Err)
+    }
+}
+
+impl<M: Metrics> Drop for BufferedMetrics<M> {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AmapMetrics, CmapMetrics};
+    use std::thread;
+
+    #[test]
+    fn test_buffered_metrics_flush() -> Result<()> {
+        let metrics = CmapMetrics::new();
+        let mut buffered = BufferedMetrics::with_flush_every(metrics.clone(), 3);
+        buffered.inc("a")?;
+        buffered.inc("b")?;
+        assert_eq!(metrics.get("a"), None);
+
+        buffered.inc("a")?;
+        assert_eq!(metrics.get("a"), Some(2));
+        assert_eq!(metrics.get("b"), Some(1));
+
+        buffered.inc("b")?;
+        drop(buffered);
+        assert_eq!(metrics.get("b"), Some(2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_buffered_metrics_flush_keeps_failed_keys() -> Result<()> {
+        let metrics = AmapMetrics::new(&["a", "b"]);
+        let mut buffered = BufferedMetrics::new(metrics.clone());
+        for key in ["a", "unknown", "b", "a"] {
+            buffered.inc(key)?;
+        }
+        assert!(buffered.flush().is_err());
+        assert_eq!(metrics.get("a"), Some(2));
+        assert_eq!(metrics.get("b"), Some(1));
+        assert!(buffered.buffer.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_buffered_metrics_recover_after_bad_key() -> Result<()> {
+        let metrics = AmapMetrics::new(&["a"]);
+        let mut buffered = BufferedMetrics::with_flush_every(metrics.clone(), 1);
+        assert!(buffered.inc("unknown").is_err());
+        for _ in 0..3 {
+            buffered.inc("a")?;
+        }
+        assert_eq!(metrics.get("a"), Some(3));
+        Ok(())
+    }
+
+    #[test]
+    fn test_buffered_metrics_across_threads() -> Result<()> {
+        let metrics = CmapMetrics::new();
+        let handles = (0..8)
+            .map(|_| {
+                let mut buffered = BufferedMetrics::new(metrics.clone());
+                thread::spawn(move || {
+                    for _ in 0..10_000 {
+                        buffered.inc("k").unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(metrics.get("k"), Some(80_000));
+        Ok(())
+    }
+}
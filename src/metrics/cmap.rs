@@ -1,15 +1,35 @@
 // metrix data structure
 // inc/snapshot
 
-use anyhow::{anyhow, Result};
+use anyhow::Result;
 use std::{
     collections::HashMap,
-    sync::{Arc, Mutex},
+    io::{self, Write},
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::{Duration, SystemTime},
 };
 
 #[derive(Debug, Clone)]
 pub struct CmapMetrics {
     data: Arc<Mutex<HashMap<String, i64>>>,
+    #[cfg(feature = "contention")]
+    contention: Arc<ContentionCounters>,
+}
+
+// How often the lock was taken, and how many of those found it held by another thread.
+// A high ratio suggests the workload would benefit from AmapMetrics or a sharded map.
+#[cfg(feature = "contention")]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ContentionStats {
+    pub acquisitions: u64,
+    pub contended: u64,
+}
+
+#[cfg(feature = "contention")]
+#[derive(Debug, Default)]
+struct ContentionCounters {
+    acquisitions: std::sync::atomic::AtomicU64,
+    contended: std::sync::atomic::AtomicU64,
 }
 
 impl Default for CmapMetrics {
@@ -22,21 +42,218 @@ impl CmapMetrics {
     pub fn new() -> Self {
         Self {
             data: Arc::new(Mutex::new(HashMap::new())),
+            #[cfg(feature = "contention")]
+            contention: Arc::default(),
         }
     }
 
     pub fn inc(&self, key: impl Into<String>) -> Result<()> {
-        let mut data = self.data.lock().map_err(|e| anyhow!(e.to_string()))?;
+        self.add(key, 1)
+    }
+
+    pub fn add(&self, key: impl Into<String>, value: i64) -> Result<()> {
+        let mut data = self.lock();
         let counter = data.entry(key.into()).or_insert(0);
-        *counter += 1;
+        *counter += value;
         Ok(())
     }
 
+    pub fn get(&self, key: impl AsRef<str>) -> Option<i64> {
+        self.lock().get(key.as_ref()).copied()
+    }
+
+    // per-second rate of `key` since `prev_value`, see `AmapMetrics::rate_since`
+    pub fn rate_since(&self, key: impl AsRef<str>, prev_value: i64, elapsed: Duration) -> f64 {
+        super::rate(self.get(key).unwrap_or(0), prev_value, elapsed)
+    }
+
     pub fn snapshot(&self) -> Result<HashMap<String, i64>> {
-        Ok(self
-            .data
-            .lock()
-            .map_err(|e| anyhow!(e.to_string()))?
-            .clone())
+        Ok(self.lock().clone())
+    }
+
+    // the counters paired with the wall-clock time they were read, for time-series stores
+    pub fn snapshot_with_timestamp(&self) -> (SystemTime, HashMap<String, i64>) {
+        let data = self.lock();
+        (SystemTime::now(), data.clone())
+    }
+
+    // stream the counters in Prometheus text format, sorted by key
+    pub fn write_prometheus<W: Write>(&self, w: &mut W, prefix: &str) -> io::Result<()> {
+        // copy out first so the lock isn't held while writing to a slow socket
+        let mut entries = self.lock().clone().into_iter().collect::<Vec<_>>();
+        entries.sort_unstable();
+        super::write_prometheus(w, prefix, entries.iter().map(|(k, v)| (k.as_str(), *v)))
+    }
+
+    pub fn to_prometheus(&self, prefix: &str) -> io::Result<String> {
+        super::to_prometheus(|buf| self.write_prometheus(buf, prefix))
+    }
+
+    // A thread panicking while holding the lock poisons it. Each critical section is a
+    // single counter update, so the map is still usable: recover it instead of failing
+    // forever. A counter whose update was interrupted by the panic may be off by one.
+    #[cfg(not(feature = "contention"))]
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, i64>> {
+        self.data.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    // try the lock first so a caller that has to wait can be counted
+    #[cfg(feature = "contention")]
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, i64>> {
+        use std::sync::{atomic::Ordering, TryLockError};
+
+        self.contention.acquisitions.fetch_add(1, Ordering::Relaxed);
+        match self.data.try_lock() {
+            Ok(guard) => guard,
+            Err(TryLockError::Poisoned(e)) => e.into_inner(),
+            Err(TryLockError::WouldBlock) => {
+                self.contention.contended.fetch_add(1, Ordering::Relaxed);
+                self.data.lock().unwrap_or_else(PoisonError::into_inner)
+            }
+        }
+    }
+
+    #[cfg(feature = "contention")]
+    pub fn contention_stats(&self) -> ContentionStats {
+        use std::sync::atomic::Ordering;
+
+        ContentionStats {
+            acquisitions: self.contention.acquisitions.load(Ordering::Relaxed),
+            contended: self.contention.contended.load(Ordering::Relaxed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_cmap_recovers_from_poison() -> Result<()> {
+        let metrics = CmapMetrics::new();
+        metrics.inc("req")?;
+
+        let poisoner = metrics.clone();
+        let _ = thread::spawn(move || {
+            let _guard = poisoner.data.lock().unwrap();
+            panic!("poison the lock");
+        })
+        .join();
+        assert!(metrics.data.is_poisoned());
+
+        metrics.inc("req")?;
+        assert_eq!(metrics.get("req"), Some(2));
+        assert_eq!(metrics.get("missing"), None);
+        assert_eq!(metrics.snapshot()?.get("req"), Some(&2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmap_snapshot_with_timestamp() -> Result<()> {
+        let metrics = CmapMetrics::new();
+        metrics.add("req", 3)?;
+        let before = SystemTime::now();
+        let (at, snapshot) = metrics.snapshot_with_timestamp();
+        assert!(at >= before && at <= SystemTime::now());
+        assert_eq!(snapshot.get("req"), Some(&3));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmap_rate_since() -> Result<()> {
+        let metrics = CmapMetrics::new();
+        metrics.add("req", 10)?;
+        metrics.add("req", 30)?;
+        assert_eq!(metrics.rate_since("req", 10, Duration::from_secs(2)), 15.0);
+        assert_eq!(metrics.rate_since("new", 0, Duration::from_secs(1)), 0.0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmap_write_prometheus() -> Result<()> {
+        let metrics = CmapMetrics::new();
+        metrics.inc("call.thread.worker.1")?;
+        metrics.inc("call.thread.worker.0")?;
+        metrics.inc("call.thread.worker.1")?;
+
+        let mut out = Vec::new();
+        metrics.write_prometheus(&mut out, "")?;
+        assert_eq!(
+            String::from_utf8(out)?,
+            "# TYPE call_thread_worker_0 counter\ncall_thread_worker_0 1\n\
+             # TYPE call_thread_worker_1 counter\ncall_thread_worker_1 2\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmap_prometheus_names() -> Result<()> {
+        let metrics = CmapMetrics::new();
+        metrics.inc("1x")?;
+        assert_eq!(metrics.to_prometheus("")?, "# TYPE _1x counter\n_1x 1\n");
+
+        metrics.inc("a.b")?;
+        metrics.inc("a_b")?;
+        let mut out = Vec::new();
+        let err = metrics.write_prometheus(&mut out, "app").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+        assert!(out.is_empty());
+        assert!(metrics.to_prometheus("app").is_err());
+        Ok(())
+    }
+
+    #[cfg(feature = "contention")]
+    #[test]
+    fn test_cmap_contention_stats() -> Result<()> {
+        let metrics = CmapMetrics::new();
+        metrics.inc("k")?;
+        metrics.snapshot()?;
+        assert_eq!(
+            metrics.contention_stats(),
+            ContentionStats {
+                acquisitions: 2,
+                contended: 0
+            }
+        );
+
+        // hold the lock so the next caller has to wait for it
+        let guard = metrics.data.lock().unwrap();
+        let waiter = metrics.clone();
+        let handle = thread::spawn(move || waiter.inc("k"));
+        while metrics.contention_stats().contended == 0 {
+            thread::yield_now();
+        }
+        drop(guard);
+        handle.join().unwrap()?;
+
+        let stats = metrics.contention_stats();
+        assert_eq!((stats.acquisitions, stats.contended), (3, 1));
+        assert_eq!(metrics.get("k"), Some(2));
+        Ok(())
+    }
+
+    #[test]
+    fn test_cmap_no_lost_updates() -> Result<()> {
+        const THREADS: i64 = 16;
+        const INCS: i64 = 100_000;
+
+        let metrics = CmapMetrics::new();
+        let handles = (0..THREADS)
+            .map(|_| {
+                let metrics = metrics.clone();
+                thread::spawn(move || {
+                    for _ in 0..INCS {
+                        metrics.inc("k").unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(metrics.snapshot()?.get("k"), Some(&(THREADS * INCS)));
+        Ok(())
     }
 }
@@ -0,0 +1,145 @@
+use std::{
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc,
+    },
+};
+
+// Lock-free counters for a closed set of keys, indexed by an enum's discriminant instead
+// of a string, so a misspelled key is a compile error rather than a "key not found" at
+// runtime. The number of counters comes from the key type, so every key has one.
+// `metric_enum!` declares a suitable enum.
+#[derive(Debug)]
+pub struct EnumMetrics<K> {
+    data: Arc<Vec<AtomicI64>>,
+    _key: PhantomData<fn(K)>,
+}
+
+/// Key type for `EnumMetrics`, implemented by `metric_enum!`. A hand-written
+/// implementation must map its `COUNT` values onto the indices `0..COUNT`.
+pub trait MetricKey: Copy {
+    const COUNT: usize;
+
+    fn index(self) -> usize;
+}
+
+impl<K: MetricKey> EnumMetrics<K> {
+    // one counter per key
+    pub fn new() -> Self {
+        Self {
+            data: Arc::new((0..K::COUNT).map(|_| AtomicI64::new(0)).collect()),
+            _key: PhantomData,
+        }
+    }
+
+    pub fn inc(&self, key: K) {
+        self.add(key, 1)
+    }
+
+    pub fn add(&self, key: K, value: i64) {
+        self.data[key.index()].fetch_add(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self, key: K) -> i64 {
+        self.data[key.index()].load(Ordering::Relaxed)
+    }
+
+    // all counters, in discriminant order
+    pub fn snapshot(&self) -> Vec<i64> {
+        self.data
+            .iter()
+            .map(|v| v.load(Ordering::Relaxed))
+            .collect()
+    }
+}
+
+impl<K: MetricKey> Default for EnumMetrics<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K> Clone for EnumMetrics<K> {
+    fn clone(&self) -> Self {
+        Self {
+            data: Arc::clone(&self.data),
+            _key: PhantomData,
+        }
+    }
+}
+
+/// Declare a fieldless enum usable as an `EnumMetrics` key, e.g.
+/// `metric_enum! { pub enum Http { Requests, Errors } }` followed by
+/// `EnumMetrics::<Http>::new()`. The enum gets `MetricKey`, `Into<usize>`, an `ALL` list
+/// of its variants, `COUNT`, and `name()`.
+#[macro_export]
+macro_rules! metric_enum {
+    ($(#[$meta:meta])* $vis:vis enum $name:ident { $($variant:ident),+ $(,)? }) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        $vis enum $name {
+            $($variant),+
+        }
+
+        impl $name {
+            pub const ALL: &'static [$name] = &[$($name::$variant),+];
+            pub const COUNT: usize = Self::ALL.len();
+
+            pub fn name(self) -> &'static str {
+                match self {
+                    $($name::$variant => stringify!($variant)),+
+                }
+            }
+        }
+
+        impl $crate::MetricKey for $name {
+            const COUNT: usize = $name::ALL.len();
+
+            fn index(self) -> usize {
+                self as usize
+            }
+        }
+
+        impl From<$name> for usize {
+            fn from(key: $name) -> usize {
+                key as usize
+            }
+        }
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    metric_enum! {
+        enum Endpoint { Home, Login, Search }
+    }
+
+    #[test]
+    fn test_enum_metrics() {
+        let metrics = EnumMetrics::<Endpoint>::new();
+        assert_eq!(metrics.snapshot().len(), Endpoint::COUNT);
+        let handles = (0..4)
+            .map(|_| {
+                let metrics = metrics.clone();
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        metrics.inc(Endpoint::Search);
+                    }
+                    metrics.add(Endpoint::Home, 2);
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        assert_eq!(metrics.get(Endpoint::Search), 4000);
+        assert_eq!(metrics.snapshot(), vec![8, 0, 4000]);
+        let names = Endpoint::ALL.iter().map(|k| k.name()).collect::<Vec<_>>();
+        assert_eq!(names, vec!["Home", "Login", "Search"]);
+    }
+}
@@ -0,0 +1,60 @@
+use std::sync::{
+    atomic::{AtomicI64, Ordering},
+    Arc,
+};
+
+// High water mark gauge: remembers the largest value ever set
+#[derive(Debug, Clone, Default)]
+pub struct MaxGauge {
+    data: Arc<AtomicI64>,
+}
+
+impl MaxGauge {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // only raises the stored value; fetch_max is the compare-and-swap max loop
+    pub fn set(&self, value: i64) {
+        self.data.fetch_max(value, Ordering::Relaxed);
+    }
+
+    pub fn get(&self) -> i64 {
+        self.data.load(Ordering::Relaxed)
+    }
+
+    // reset to 0, returning the peak observed so far
+    pub fn reset(&self) -> i64 {
+        self.data.swap(0, Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn test_max_gauge_keeps_peak() {
+        let gauge = MaxGauge::new();
+        let handles = (0..8)
+            .map(|t| {
+                let gauge = gauge.clone();
+                thread::spawn(move || {
+                    for v in 0..1000 {
+                        gauge.set(t * 1000 + v);
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert_eq!(gauge.get(), 7999);
+
+        gauge.set(10);
+        assert_eq!(gauge.get(), 7999);
+        assert_eq!(gauge.reset(), 7999);
+        assert_eq!(gauge.get(), 0);
+    }
+}
@@ -0,0 +1,146 @@
+use super::Metrics;
+use anyhow::{anyhow, Result};
+use memmap2::MmapMut;
+use std::{
+    collections::HashMap,
+    fs::OpenOptions,
+    mem,
+    path::Path,
+    sync::{
+        atomic::{AtomicI64, Ordering},
+        Arc,
+    },
+};
+
+// Atomic counters living in a memory-mapped file. The file holds one i64 slot per
+// metric name, in the order the names were given, so counts survive a restart and any
+// process opening the file with the same names sees the same counters.
+#[derive(Debug, Clone)]
+pub struct MmapMetrics {
+    index: Arc<HashMap<&'static str, usize>>,
+    map: Arc<MmapMut>,
+}
+
+impl MmapMetrics {
+    pub fn open(path: impl AsRef<Path>, metrics_names: &[&'static str]) -> Result<Self> {
+        let mut index = HashMap::new();
+        for &name in metrics_names {
+            let next = index.len();
+            index.entry(name).or_insert(next);
+        }
+        if index.is_empty() {
+            return Err(anyhow!("at least one metric name is required"));
+        }
+
+        let len = (index.len() * mem::size_of::<AtomicI64>()) as u64;
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        match file.metadata()?.len() {
+            0 => file.set_len(len)?,
+            existing if existing != len => {
+                return Err(anyhow!(
+                    "metrics file has {} bytes, expected {} for {} metrics",
+                    existing,
+                    len,
+                    index.len()
+                ))
+            }
+            _ => {}
+        }
+        // SAFETY: the mapping is kept alive by the Arc for as long as any clone exists;
+        // concurrent writers (threads or processes) only touch it through atomics.
+        let map = unsafe { MmapMut::map_mut(&file)? };
+
+        Ok(Self {
+            index: Arc::new(index),
+            map: Arc::new(map),
+        })
+    }
+
+    pub fn inc(&self, key: impl AsRef<str>) -> Result<()> {
+        self.add(key, 1)
+    }
+
+    pub fn add(&self, key: impl AsRef<str>, value: i64) -> Result<()> {
+        let key = key.as_ref();
+        let idx = *self
+            .index
+            .get(key)
+            .ok_or_else(|| anyhow!("key {} not found", key))?;
+        self.counter(idx).fetch_add(value, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub fn snapshot(&self) -> HashMap<String, i64> {
+        self.index
+            .iter()
+            .map(|(k, &idx)| (k.to_string(), self.counter(idx).load(Ordering::Relaxed)))
+            .collect()
+    }
+
+    // write dirty pages back to the file now instead of whenever the OS decides to
+    pub fn flush(&self) -> Result<()> {
+        Ok(self.map.flush()?)
+    }
+
+    fn counter(&self, idx: usize) -> &AtomicI64 {
+        debug_assert!((idx + 1) * mem::size_of::<AtomicI64>() <= self.map.len());
+        // SAFETY: idx is in bounds (checked at open), the mapping is page aligned so every
+        // slot is aligned for AtomicI64, and all accesses go through atomics.
+        unsafe { &*(self.map.as_ptr() as *const AtomicI64).add(idx) }
+    }
+}
+
+impl Metrics for MmapMetrics {
+    fn add(&self, key: &str, value: i64) -> Result<()> {
+        MmapMetrics::add(self, key, value)
+    }
+
+    fn snapshot(&self) -> Result<HashMap<String, i64>> {
+        Ok(MmapMetrics::snapshot(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, thread};
+
+    #[test]
+    fn test_mmap_metrics_persist() -> Result<()> {
+        let path = env::temp_dir().join(format!("concurrency-mmap-{}.bin", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let metrics = MmapMetrics::open(&path, &["req", "err"])?;
+        let handles = (0..4)
+            .map(|_| {
+                let metrics = metrics.clone();
+                thread::spawn(move || {
+                    for _ in 0..1000 {
+                        metrics.inc("req").unwrap();
+                    }
+                })
+            })
+            .collect::<Vec<_>>();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        metrics.inc("err")?;
+        assert!(metrics.inc("missing").is_err());
+        metrics.flush()?;
+        drop(metrics);
+
+        let reopened = MmapMetrics::open(&path, &["req", "err"])?;
+        let snapshot = reopened.snapshot();
+        assert_eq!(snapshot.get("req"), Some(&4000));
+        assert_eq!(snapshot.get("err"), Some(&1));
+        assert!(MmapMetrics::open(&path, &["req"]).is_err());
+
+        fs::remove_file(&path)?;
+        Ok(())
+    }
+}
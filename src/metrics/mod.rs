@@ -1,5 +1,123 @@
 mod amap;
+mod bounded;
+mod buffered;
 mod cmap;
+mod enum_keyed;
+mod gauge;
+#[cfg(feature = "mmap")]
+mod mmap;
+mod timestamped;
 
 pub use amap::*;
+pub use bounded::*;
+pub use buffered::*;
 pub use cmap::*;
+pub use enum_keyed::*;
+pub use gauge::*;
+#[cfg(feature = "mmap")]
+pub use mmap::*;
+pub use timestamped::*;
+
+use anyhow::Result;
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+    time::Duration,
+};
+
+// common surface of the counter maps, so callers can swap backends
+pub trait Metrics {
+    fn add(&self, key: &str, value: i64) -> Result<()>;
+    fn snapshot(&self) -> Result<HashMap<String, i64>>;
+
+    fn inc(&self, key: &str) -> Result<()> {
+        self.add(key, 1)
+    }
+}
+
+impl Metrics for AmapMetrics {
+    fn add(&self, key: &str, value: i64) -> Result<()> {
+        AmapMetrics::add(self, key, value)
+    }
+
+    fn snapshot(&self) -> Result<HashMap<String, i64>> {
+        Ok(AmapMetrics::snapshot(self))
+    }
+}
+
+impl Metrics for CmapMetrics {
+    fn add(&self, key: &str, value: i64) -> Result<()> {
+        CmapMetrics::add(self, key, value)
+    }
+
+    fn snapshot(&self) -> Result<HashMap<String, i64>> {
+        CmapMetrics::snapshot(self)
+    }
+}
+
+// Prometheus text exposition: one counter per key, with characters Prometheus doesn't
+// allow in metric names (e.g. the dots in "req.page.1") replaced by underscores. Two
+// keys that end up with the same name (e.g. "a.b" and "a_b") are an InvalidInput error,
+// reported before anything is written.
+fn write_prometheus<'a, W: Write>(
+    w: &mut W,
+    prefix: &str,
+    entries: impl IntoIterator<Item = (&'a str, i64)>,
+) -> io::Result<()> {
+    let mut seen = HashMap::new();
+    let mut named = Vec::new();
+    for (key, value) in entries {
+        let name = prometheus_name(prefix, key);
+        if let Some(other) = seen.insert(name.clone(), key) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("keys {:?} and {:?} both export as {}", other, key, name),
+            ));
+        }
+        named.push((name, value));
+    }
+    for (name, value) in named {
+        writeln!(w, "# TYPE {} counter", name)?;
+        writeln!(w, "{} {}", name, value)?;
+    }
+    Ok(())
+}
+
+// metric names match [a-zA-Z_:][a-zA-Z0-9_:]*, so a leading digit gets an underscore
+fn prometheus_name(prefix: &str, key: &str) -> String {
+    let name = if prefix.is_empty() {
+        key.to_string()
+    } else {
+        format!("{}_{}", prefix, key)
+    };
+    let name = name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | ':' => c,
+            _ => '_',
+        })
+        .collect::<String>();
+    match name.chars().next() {
+        None | Some('0'..='9') => format!("_{}", name),
+        Some(_) => name,
+    }
+}
+
+// change per second between two readings of a counter
+fn rate(current: i64, prev: i64, elapsed: Duration) -> f64 {
+    if elapsed.is_zero() {
+        return 0.0;
+    }
+    (current - prev) as f64 / elapsed.as_secs_f64()
+}
+
+// only fails on colliding metric names; writing into a Vec itself can't fail
+fn to_prometheus<F>(write: F) -> io::Result<String>
+where
+    F: FnOnce(&mut Vec<u8>) -> io::Result<()>,
+{
+    let mut buf = Vec::new();
+    write(&mut buf)?;
+    // only valid UTF-8 is ever written
+    Ok(String::from_utf8(buf).expect("prometheus output is UTF-8"))
+}
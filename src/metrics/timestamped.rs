@@ -0,0 +1,103 @@
+use super::Metrics;
+use anyhow::Result;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    time::SystemTime,
+};
+
+// Like CmapMetrics, but also remembers when each key was first and last updated, to
+// spot stale or newly appearing metrics in a long-running service.
+#[derive(Debug, Clone, Default)]
+pub struct TimestampedMetrics {
+    data: Arc<Mutex<HashMap<String, TimestampedCount>>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TimestampedCount {
+    pub count: i64,
+    pub first_seen: SystemTime,
+    pub last_seen: SystemTime,
+}
+
+impl TimestampedMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn inc(&self, key: impl Into<String>) -> Result<()> {
+        self.add(key, 1)
+    }
+
+    pub fn add(&self, key: impl Into<String>, value: i64) -> Result<()> {
+        let now = SystemTime::now();
+        let mut data = self.lock();
+        let entry = data.entry(key.into()).or_insert(TimestampedCount {
+            count: 0,
+            first_seen: now,
+            last_seen: now,
+        });
+        entry.count += value;
+        entry.last_seen = now;
+        Ok(())
+    }
+
+    pub fn get(&self, key: impl AsRef<str>) -> Option<TimestampedCount> {
+        self.lock().get(key.as_ref()).copied()
+    }
+
+    pub fn snapshot(&self) -> Result<HashMap<String, i64>> {
+        Ok(self
+            .lock()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.count))
+            .collect())
+    }
+
+    pub fn snapshot_timestamped(&self) -> HashMap<String, TimestampedCount> {
+        self.lock().clone()
+    }
+
+    // same poison recovery as CmapMetrics
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, TimestampedCount>> {
+        self.data.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Metrics for TimestampedMetrics {
+    fn add(&self, key: &str, value: i64) -> Result<()> {
+        TimestampedMetrics::add(self, key, value)
+    }
+
+    fn snapshot(&self) -> Result<HashMap<String, i64>> {
+        TimestampedMetrics::snapshot(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{thread, time::Duration};
+
+    #[test]
+    fn test_timestamped_first_and_last_seen() -> Result<()> {
+        let metrics = TimestampedMetrics::new();
+        let start = SystemTime::now();
+        metrics.inc("req")?;
+        let first = metrics.get("req").unwrap();
+        assert_eq!(first.count, 1);
+        assert!(first.first_seen >= start);
+        assert_eq!(first.first_seen, first.last_seen);
+
+        thread::sleep(Duration::from_millis(5));
+        metrics.add("req", 2)?;
+        let second = metrics.snapshot_timestamped()["req"];
+        assert_eq!(second.count, 3);
+        assert_eq!(second.first_seen, first.first_seen);
+        assert!(second.last_seen > first.last_seen);
+
+        assert_eq!(metrics.snapshot()?.get("req"), Some(&3));
+        assert!(metrics.get("missing").is_none());
+        Ok(())
+    }
+}
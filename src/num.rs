@@ -0,0 +1,326 @@
+use std::{
+    cmp::Ordering,
+    fmt::Debug,
+    num::Wrapping,
+    ops::{Add, AddAssign, Mul},
+};
+
+/// Element requirements for the threaded matrix operations. Blanket-implemented, so any
+/// type with these capabilities qualifies: the primitive integers and floats, their
+/// `Wrapping` forms, and `Complex` with the `num-complex` feature. A custom numeric type
+/// only needs a `FusedMulAdd` impl on top, usually `self * rhs + acc`.
+pub trait Numeric:
+    Copy
+    + Default
+    + Add<Output = Self>
+    + AddAssign
+    + Mul<Output = Self>
+    + FusedMulAdd
+    + Debug
+    + Send
+    + 'static
+{
+}
+
+impl<T> Numeric for T where
+    T: Copy
+        + Default
+        + Add<Output = T>
+        + AddAssign
+        + Mul<Output = T>
+        + FusedMulAdd
+        + Debug
+        + Send
+        + 'static
+{
+}
+
+/// Multiply-accumulate used by `dot_product`: `self * rhs + acc`.
+///
+/// On targets with hardware FMA (e.g. built with `-C target-feature=+fma`), floating point
+/// types compute it with a single rounding via `mul_add`, so results can differ from a
+/// plain multiply-then-add in the last bits (usually for the better). Elsewhere `mul_add`
+/// is a software libm call, several times slower than `*` and `+`, so it isn't used.
+/// Integer types wrap on overflow, in debug and release builds alike.
+pub trait FusedMulAdd: Sized {
+    fn fused_mul_add(self, rhs: Self, acc: Self) -> Self;
+}
+
+macro_rules! impl_wrapping_mul_add {
+    ($($t:ty),*) => {
+        $(
+            impl FusedMulAdd for $t {
+                #[inline]
+                fn fused_mul_add(self, rhs: Self, acc: Self) -> Self {
+                    self.wrapping_mul(rhs).wrapping_add(acc)
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_plain_mul_add {
+    ($($t:ty),*) => {
+        $(
+            impl FusedMulAdd for $t {
+                #[inline]
+                fn fused_mul_add(self, rhs: Self, acc: Self) -> Self {
+                    self * rhs + acc
+                }
+            }
+        )*
+    };
+}
+
+#[cfg(target_feature = "fma")]
+macro_rules! impl_fused_mul_add {
+    ($($t:ty),*) => {
+        $(
+            impl FusedMulAdd for $t {
+                #[inline]
+                fn fused_mul_add(self, rhs: Self, acc: Self) -> Self {
+                    self.mul_add(rhs, acc)
+                }
+            }
+        )*
+    };
+}
+
+impl_wrapping_mul_add!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+#[cfg(target_feature = "fma")]
+impl_fused_mul_add!(f32, f64);
+#[cfg(not(target_feature = "fma"))]
+impl_plain_mul_add!(f32, f64);
+// `*` and `+` already wrap
+impl_plain_mul_add!(
+    Wrapping<i8>,
+    Wrapping<i16>,
+    Wrapping<i32>,
+    Wrapping<i64>,
+    Wrapping<i128>,
+    Wrapping<isize>,
+    Wrapping<u8>,
+    Wrapping<u16>,
+    Wrapping<u32>,
+    Wrapping<u64>,
+    Wrapping<u128>,
+    Wrapping<usize>
+);
+
+#[cfg(feature = "num-complex")]
+impl_plain_mul_add!(num_complex::Complex<f32>, num_complex::Complex<f64>);
+
+/// Addition with the overflow behaviour of `FusedMulAdd`, for plain sums such as
+/// `Matrix::trace`: integers wrap in every build, other types add normally.
+pub trait WrappingAdd {
+    fn wrapping_add(self, rhs: Self) -> Self;
+}
+
+macro_rules! impl_wrapping_add {
+    ($($t:ty),*) => {
+        $(
+            impl WrappingAdd for $t {
+                #[inline]
+                fn wrapping_add(self, rhs: Self) -> Self {
+                    <$t>::wrapping_add(self, rhs)
+                }
+            }
+        )*
+    };
+}
+
+macro_rules! impl_plain_add {
+    ($($t:ty),*) => {
+        $(
+            impl WrappingAdd for $t {
+                #[inline]
+                fn wrapping_add(self, rhs: Self) -> Self {
+                    self + rhs
+                }
+            }
+        )*
+    };
+}
+
+impl_wrapping_add!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+impl_plain_add!(f32, f64);
+impl_plain_add!(
+    Wrapping<i8>,
+    Wrapping<i16>,
+    Wrapping<i32>,
+    Wrapping<i64>,
+    Wrapping<i128>,
+    Wrapping<isize>,
+    Wrapping<u8>,
+    Wrapping<u16>,
+    Wrapping<u32>,
+    Wrapping<u64>,
+    Wrapping<u128>,
+    Wrapping<usize>
+);
+
+#[cfg(feature = "num-complex")]
+impl_plain_add!(num_complex::Complex<f32>, num_complex::Complex<f64>);
+
+/// Integer multiply-accumulate that saturates or reports overflow instead of wrapping.
+pub trait OverflowArith: Sized {
+    fn checked_mul_add(self, rhs: Self, acc: Self) -> Option<Self>;
+    fn saturating_mul_add(self, rhs: Self, acc: Self) -> Self;
+
+    /// Exact `sum(a[k] * b[k])` over the common length, however the intermediate sums
+    /// overflow `Self`: `Ok` if the final value fits, otherwise which side of the range
+    /// it falls on (`Greater` above `MAX`, `Less` below `MIN`).
+    fn exact_dot(a: &[Self], b: &[Self]) -> Result<Self, Ordering>;
+
+    // `exact_dot` clamped to the range of `Self`
+    fn saturating_dot(a: &[Self], b: &[Self]) -> Self;
+}
+
+// 256-bit two's complement accumulator, `hi * 2^128 + lo`. Every product of two 128-bit
+// (or narrower) integers fits; the sum counts how often `hi` itself wraps.
+#[derive(Default)]
+struct Wide {
+    hi: i128,
+    lo: u128,
+    wraps: i64,
+}
+
+impl Wide {
+    fn add(&mut self, (hi, lo): (i128, u128)) {
+        let (lo, carry) = self.lo.overflowing_add(lo);
+        self.lo = lo;
+        for addend in [hi, i128::from(carry)] {
+            let (sum, wrapped) = self.hi.overflowing_add(addend);
+            if wrapped {
+                self.wraps += if addend > 0 { 1 } else { -1 };
+            }
+            self.hi = sum;
+        }
+    }
+
+    // the value as an i128, or the side of the i128 range it falls on
+    fn to_i128(&self) -> Result<i128, Ordering> {
+        let fits = match self.hi {
+            0 => self.lo <= i128::MAX as u128,
+            -1 => self.lo > i128::MAX as u128,
+            _ => false,
+        };
+        match self.wraps.cmp(&0) {
+            Ordering::Equal if fits => Ok(self.lo as i128),
+            Ordering::Equal if self.hi < 0 => Err(Ordering::Less),
+            Ordering::Equal => Err(Ordering::Greater),
+            side => Err(side),
+        }
+    }
+}
+
+// full 256-bit product of two u128s as (hi, lo)
+fn widening_mul(a: u128, b: u128) -> (u128, u128) {
+    const MASK: u128 = u64::MAX as u128;
+    let (a1, a0, b1, b0) = (a >> 64, a & MASK, b >> 64, b & MASK);
+    let (p00, p01, p10, p11) = (a0 * b0, a0 * b1, a1 * b0, a1 * b1);
+    let mid = (p00 >> 64) + (p01 & MASK) + (p10 & MASK);
+    let lo = (p00 & MASK) | (mid << 64);
+    let hi = p11 + (p01 >> 64) + (p10 >> 64) + (mid >> 64);
+    (hi, lo)
+}
+
+// exact signed product in the accumulator's (hi, lo) form
+fn signed_product(a: i128, b: i128) -> (i128, u128) {
+    let (hi, lo) = widening_mul(a.unsigned_abs(), b.unsigned_abs());
+    // at most 2^254, so `hi` is non-negative as an i128
+    let (hi, lo) = (hi as i128, lo);
+    if (a < 0) != (b < 0) {
+        let (lo, carry) = (!lo).overflowing_add(1);
+        (!hi + i128::from(carry), lo)
+    } else {
+        (hi, lo)
+    }
+}
+
+macro_rules! impl_overflow_arith {
+    (signed: $($t:ty),*) => {
+        $(
+            impl OverflowArith for $t {
+                impl_overflow_arith!(@methods);
+
+                fn exact_dot(a: &[Self], b: &[Self]) -> Result<Self, Ordering> {
+                    let mut sum = Wide::default();
+                    for (&x, &y) in a.iter().zip(b) {
+                        sum.add(signed_product(x as i128, y as i128));
+                    }
+                    let v = sum.to_i128()?;
+                    <$t>::try_from(v).map_err(|_| v.cmp(&0))
+                }
+            }
+        )*
+    };
+    (unsigned: $($t:ty),*) => {
+        $(
+            impl OverflowArith for $t {
+                impl_overflow_arith!(@methods);
+
+                fn exact_dot(a: &[Self], b: &[Self]) -> Result<Self, Ordering> {
+                    // no negative terms, so once the sum is past u128 it stays there
+                    let mut sum = 0u128;
+                    for (&x, &y) in a.iter().zip(b) {
+                        let (hi, lo) = widening_mul(x as u128, y as u128);
+                        if hi != 0 {
+                            return Err(Ordering::Greater);
+                        }
+                        sum = sum.checked_add(lo).ok_or(Ordering::Greater)?;
+                    }
+                    <$t>::try_from(sum).map_err(|_| Ordering::Greater)
+                }
+            }
+        )*
+    };
+    // shared by both arms
+    (@methods) => {
+        #[inline]
+        fn checked_mul_add(self, rhs: Self, acc: Self) -> Option<Self> {
+            self.checked_mul(rhs)?.checked_add(acc)
+        }
+
+        #[inline]
+        fn saturating_mul_add(self, rhs: Self, acc: Self) -> Self {
+            self.saturating_mul(rhs).saturating_add(acc)
+        }
+
+        fn saturating_dot(a: &[Self], b: &[Self]) -> Self {
+            match Self::exact_dot(a, b) {
+                Ok(v) => v,
+                Err(Ordering::Less) => Self::MIN,
+                Err(_) => Self::MAX,
+            }
+        }
+    };
+}
+
+impl_overflow_arith!(signed: i8, i16, i32, i64, i128, isize);
+impl_overflow_arith!(unsigned: u8, u16, u32, u64, u128, usize);
+
+/// Primitive `as` conversion, which may truncate, wrap or round (e.g. `f64 -> i32`).
+pub trait LossyFrom<T> {
+    fn lossy_from(value: T) -> Self;
+}
+
+macro_rules! impl_lossy_from {
+    ($($from:ty),*) => {
+        $(
+            impl_lossy_from!(@to $from; i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64);
+        )*
+    };
+    (@to $from:ty; $($to:ty),*) => {
+        $(
+            impl LossyFrom<$from> for $to {
+                #[inline]
+                fn lossy_from(value: $from) -> Self {
+                    value as $to
+                }
+            }
+        )*
+    };
+}
+
+impl_lossy_from!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize, f32, f64);
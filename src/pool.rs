@@ -0,0 +1,134 @@
+use anyhow::{anyhow, Result};
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, Mutex, PoisonError,
+    },
+    thread::{self, JoinHandle},
+};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+// Fixed set of persistent worker threads pulling jobs off a shared channel
+pub struct ThreadPool {
+    sender: Option<mpsc::Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+    live: Arc<AtomicUsize>,
+}
+
+impl ThreadPool {
+    pub fn new(size: usize) -> Result<Self> {
+        if size == 0 {
+            return Err(anyhow!("Thread pool needs at least one worker"));
+        }
+
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let live = Arc::new(AtomicUsize::new(0));
+        let workers = (0..size)
+            .map(|_| {
+                let receiver = Arc::clone(&receiver);
+                let live = Arc::clone(&live);
+                live.fetch_add(1, Ordering::SeqCst);
+                thread::spawn(move || {
+                    let _live = LiveGuard(live);
+                    loop {
+                        // the guard is dropped before the job runs
+                        let job = receiver
+                            .lock()
+                            .unwrap_or_else(PoisonError::into_inner)
+                            .recv();
+                        match job {
+                            Ok(job) => job(),
+                            // sender dropped: the pool is shutting down
+                            Err(_) => break,
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        Ok(Self {
+            sender: Some(sender),
+            workers,
+            live,
+        })
+    }
+
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    // workers still running; a job that panics takes its worker down with it
+    pub fn live_workers(&self) -> usize {
+        self.live.load(Ordering::SeqCst)
+    }
+
+    pub fn execute<F>(&self, f: F) -> Result<()>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        self.sender
+            .as_ref()
+            .ok_or_else(|| anyhow!("Thread pool is shut down"))?
+            .send(Box::new(f))
+            .map_err(|_| anyhow!("All thread pool workers have exited"))
+    }
+}
+
+// decrements the live worker count when a worker exits, even by panicking
+struct LiveGuard(Arc<AtomicUsize>);
+
+impl Drop for LiveGuard {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl Drop for ThreadPool {
+    // Close the job channel so every worker's recv fails once the queue is drained, then
+    // wait for all of them. Drop can't return errors, so worker panics are only logged.
+    fn drop(&mut self) {
+        drop(self.sender.take());
+        for (idx, worker) in self.workers.drain(..).enumerate() {
+            if let Err(e) = worker.join() {
+                eprintln!("thread pool worker {} panicked: {:?}", idx, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pool_runs_jobs() -> Result<()> {
+        let counter = Arc::new(AtomicUsize::new(0));
+        let pool = ThreadPool::new(4)?;
+        for _ in 0..100 {
+            let counter = Arc::clone(&counter);
+            pool.execute(move || {
+                counter.fetch_add(1, Ordering::SeqCst);
+            })?;
+        }
+        // drop waits for the queued jobs to drain
+        drop(pool);
+        assert_eq!(counter.load(Ordering::SeqCst), 100);
+        assert!(ThreadPool::new(0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_pool_drop_joins_all_workers() -> Result<()> {
+        for _ in 0..50 {
+            let pool = ThreadPool::new(4)?;
+            let live = Arc::clone(&pool.live);
+            pool.execute(|| panic!("job failure"))?;
+            pool.execute(|| {})?;
+            drop(pool);
+            assert_eq!(live.load(Ordering::SeqCst), 0);
+        }
+        Ok(())
+    }
+}
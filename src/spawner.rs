@@ -0,0 +1,35 @@
+use crate::ThreadPool;
+use std::thread;
+
+pub type Task = Box<dyn FnOnce() + Send + 'static>;
+
+// Where `multiply_in_pool` runs its work, so callers can reuse a pool they already own
+// instead of this crate spawning threads. A spawner that drops a task without running
+// it makes the multiply fail rather than hang.
+pub trait Spawner {
+    fn spawn(&self, f: Task);
+}
+
+// a fresh std thread per task
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StdSpawner;
+
+impl Spawner for StdSpawner {
+    fn spawn(&self, f: Task) {
+        thread::spawn(f);
+    }
+}
+
+impl Spawner for ThreadPool {
+    fn spawn(&self, f: Task) {
+        // on failure the task is dropped, which the waiting side reports
+        let _ = self.execute(f);
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl Spawner for rayon::ThreadPool {
+    fn spawn(&self, f: Task) {
+        rayon::ThreadPool::spawn(self, f);
+    }
+}
@@ -1,6 +1,10 @@
+use crate::FusedMulAdd;
 use anyhow::{anyhow, Result};
 use std::ops::{Add, AddAssign, Deref, Mul};
 
+#[cfg(feature = "num-complex")]
+use num_complex::Complex;
+
 pub struct Vector<T> {
     data: Vec<T>,
 }
@@ -19,17 +23,284 @@ impl<T> Vector<T> {
     }
 }
 
+impl<T> Vector<T> {
+    // like `Matrix::expect_shape`: panics in debug builds unless this has `len` elements
+    #[track_caller]
+    pub fn expect_len(&self, len: usize) -> &Self {
+        debug_assert!(
+            self.len() == len,
+            "expected a vector of length {}, got {}",
+            len,
+            self.len()
+        );
+        self
+    }
+}
+
+impl<T: Copy> Vector<T> {
+    // extend with `fill`, or truncate, to exactly `new_len` elements
+    pub fn padded(&self, new_len: usize, fill: T) -> Vector<T> {
+        let mut data = self.data[..new_len.min(self.len())].to_vec();
+        data.resize(new_len, fill);
+        Vector::new(data)
+    }
+}
+
+impl Vector<f64> {
+    // Euclidean (L2) norm
+    pub fn norm(&self) -> f64 {
+        self.iter().map(|v| v * v).sum::<f64>().sqrt()
+    }
+
+    // unit vector in the same direction, errors on the zero vector
+    pub fn normalize(&self) -> Result<Vector<f64>> {
+        let mut v = Vector::new(self.data.clone());
+        v.normalize_in_place()?;
+        Ok(v)
+    }
+
+    // like `normalize`, but rescales this vector without allocating
+    pub fn normalize_in_place(&mut self) -> Result<()> {
+        let norm = self.norm();
+        if norm == 0.0 {
+            return Err(anyhow!("Cannot normalize a zero vector"));
+        }
+        for v in self.data.iter_mut() {
+            *v /= norm;
+        }
+        Ok(())
+    }
+
+    pub fn approx_eq(&self, other: &Vector<f64>, epsilon: f64) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .zip(other.iter())
+                .all(|(a, b)| (a - b).abs() <= epsilon)
+    }
+
+    /// Mean and population variance in a single pass, using Welford's online algorithm to
+    /// avoid the cancellation of the naive sum-of-squares formula.
+    pub fn mean_variance(&self) -> Result<(f64, f64)> {
+        if self.is_empty() {
+            return Err(anyhow!("Mean and variance of an empty vector"));
+        }
+        let (mut mean, mut m2) = (0.0, 0.0);
+        for (i, &x) in self.iter().enumerate() {
+            let delta = x - mean;
+            mean += delta / (i + 1) as f64;
+            m2 += delta * (x - mean);
+        }
+        Ok((mean, m2 / self.len() as f64))
+    }
+}
+
+#[cfg(feature = "num-complex")]
+impl Vector<Complex<f64>> {
+    pub fn conjugate(&self) -> Self {
+        Self::new(self.data.iter().map(|v| v.conj()).collect::<Vec<_>>())
+    }
+}
+
+/// Sum of the element-wise products. Integer types wrap on overflow in every build,
+/// see `FusedMulAdd`; `dot_product_checked_nan` guards the float case.
 pub fn dot_product<T>(a: Vector<T>, b: Vector<T>) -> Result<T>
 where
-    T: Copy + Default + Add<Output = T> + AddAssign + Mul<Output = T>,
+    T: Copy + Default + Add<Output = T> + AddAssign + Mul<Output = T> + FusedMulAdd,
+{
+    dot_product_slice(&a, &b)
+}
+
+/// Like `dot_product`, but errors on a NaN or infinite element in either input, or a
+/// non-finite result (e.g. from overflow), instead of quietly returning NaN or infinity.
+pub fn dot_product_checked_nan(a: Vector<f64>, b: Vector<f64>) -> Result<f64> {
+    for (name, v) in [("first", &a), ("second", &b)] {
+        if let Some(idx) = v.iter().position(|x| !x.is_finite()) {
+            return Err(anyhow!(
+                "Non-finite element {} at index {} of the {} vector",
+                v[idx],
+                idx,
+                name
+            ));
+        }
+    }
+    let result = dot_product(a, b)?;
+    if !result.is_finite() {
+        return Err(anyhow!("Dot product is not finite: {}", result));
+    }
+    Ok(result)
+}
+
+// dot product over borrowed slices, e.g. matrix row views, without copying into a Vector
+pub fn dot_product_slice<T>(a: &[T], b: &[T]) -> Result<T>
+where
+    T: Copy + Default + Add<Output = T> + AddAssign + Mul<Output = T> + FusedMulAdd,
 {
     if a.len() != b.len() {
         return Err(anyhow!("Incompatible vector dimensions"));
     }
     let mut result = T::default();
     for i in 0..a.len() {
-        result += a[i] * b[i];
+        result = a[i].fused_mul_add(b[i], result);
     }
 
     Ok(result)
 }
+
+// larger of each pair of elements; keeps `a`'s element on ties or unordered values (NaN)
+pub fn elementwise_max<T: Copy + PartialOrd>(a: &Vector<T>, b: &Vector<T>) -> Result<Vector<T>> {
+    zip_with(a, b, |x, y| if y > x { y } else { x })
+}
+
+// smaller of each pair of elements; keeps `a`'s element on ties or unordered values (NaN)
+pub fn elementwise_min<T: Copy + PartialOrd>(a: &Vector<T>, b: &Vector<T>) -> Result<Vector<T>> {
+    zip_with(a, b, |x, y| if y < x { y } else { x })
+}
+
+fn zip_with<T: Copy>(a: &Vector<T>, b: &Vector<T>, f: impl Fn(T, T) -> T) -> Result<Vector<T>> {
+    if a.len() != b.len() {
+        return Err(anyhow!("Incompatible vector dimensions"));
+    }
+    Ok(Vector::new(
+        a.iter()
+            .zip(b.iter())
+            .map(|(&x, &y)| f(x, y))
+            .collect::<Vec<_>>(),
+    ))
+}
+
+// Euclidean distance between two equal-length slices, e.g. matrix row views
+pub fn euclidean_distance(a: &[f64], b: &[f64]) -> Result<f64> {
+    if a.len() != b.len() {
+        return Err(anyhow!("Incompatible vector dimensions"));
+    }
+    Ok(a.iter()
+        .zip(b)
+        .map(|(x, y)| (x - y) * (x - y))
+        .sum::<f64>()
+        .sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::num::Wrapping;
+
+    #[test]
+    fn test_vector_padded() {
+        let v = Vector::new(vec![1, 2, 3]);
+        assert_eq!(*v.padded(5, 0), vec![1, 2, 3, 0, 0]);
+        assert_eq!(*v.padded(2, 0), vec![1, 2]);
+        assert_eq!(*v.padded(3, 0), vec![1, 2, 3]);
+    }
+
+    #[cfg(debug_assertions)]
+    #[test]
+    #[should_panic(expected = "expected a vector of length 2, got 3")]
+    fn test_vector_expect_len() {
+        let v = Vector::new(vec![1, 2, 3]);
+        assert_eq!(v.expect_len(3).len(), 3);
+        v.expect_len(2);
+    }
+
+    #[test]
+    fn test_vector_norm() {
+        assert_eq!(Vector::new(vec![3.0, 4.0]).norm(), 5.0);
+        assert_eq!(Vector::new(Vec::<f64>::new()).norm(), 0.0);
+    }
+
+    #[test]
+    fn test_vector_normalize() -> Result<()> {
+        let mut v = Vector::new(vec![3.0, 0.0, -4.0]);
+        assert_eq!(*v.normalize()?, vec![0.6, 0.0, -0.8]);
+        v.normalize_in_place()?;
+        assert_eq!(*v, vec![0.6, 0.0, -0.8]);
+
+        let mut zero = Vector::new(vec![0.0, 0.0]);
+        assert!(zero.normalize_in_place().is_err());
+        assert!(Vector::new(Vec::<f64>::new()).normalize().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_vector_approx_eq() {
+        let a = Vector::new(vec![1.0, 2.0, 3.0]);
+        let b = Vector::new(vec![1.0 + 1e-12, 2.0, 3.0 - 1e-12]);
+        assert!(a.approx_eq(&b, 1e-9));
+        assert!(!a.approx_eq(&b, 1e-15));
+        assert!(!a.approx_eq(&Vector::new(vec![1.0, 2.0]), 1e-9));
+    }
+
+    #[test]
+    fn test_vector_mean_variance() -> Result<()> {
+        let v = Vector::new(vec![2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]);
+        assert_eq!(v.mean_variance()?, (5.0, 4.0));
+
+        // a large offset ruins the naive E[x^2] - E[x]^2 but not Welford
+        let shifted = Vector::new(v.iter().map(|x| x + 1e9).collect::<Vec<_>>());
+        let (mean, variance) = shifted.mean_variance()?;
+        assert_eq!(mean, 1e9 + 5.0);
+        assert!((variance - 4.0).abs() < 1e-6);
+
+        assert!(Vector::new(Vec::<f64>::new()).mean_variance().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_elementwise_max_min() -> Result<()> {
+        // the vectors cross over, so both contribute to each result
+        let a = Vector::new(vec![1, 5, 3, 0]);
+        let b = Vector::new(vec![4, 2, 3, -1]);
+        assert_eq!(*elementwise_max(&a, &b)?, vec![4, 5, 3, 0]);
+        assert_eq!(*elementwise_min(&a, &b)?, vec![1, 2, 3, -1]);
+        assert!(elementwise_max(&a, &Vector::new(vec![1])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_dot_product_checked_nan() -> Result<()> {
+        let v = |data: &[f64]| Vector::new(data.to_vec());
+        assert_eq!(
+            dot_product_checked_nan(v(&[1.0, 2.0]), v(&[3.0, 4.0]))?,
+            11.0
+        );
+
+        let err = dot_product_checked_nan(v(&[1.0, 2.0]), v(&[3.0, f64::NAN])).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Non-finite element NaN at index 1 of the second vector"
+        );
+        assert!(dot_product_checked_nan(v(&[f64::INFINITY]), v(&[1.0])).is_err());
+        assert!(dot_product_checked_nan(v(&[f64::MAX]), v(&[2.0])).is_err());
+        assert!(dot_product(v(&[f64::NAN]), v(&[1.0]))?.is_nan());
+        Ok(())
+    }
+
+    #[test]
+    fn test_dot_product_uses_fma_when_available() -> Result<()> {
+        // x * x is not representable; only a fused multiply-add keeps the 2^-60 term
+        let x = 1.0 + 2f64.powi(-30);
+        let a = Vector::new(vec![-(1.0 + 2f64.powi(-29)), x]);
+        let b = Vector::new(vec![1.0, x]);
+        let expected = if cfg!(target_feature = "fma") {
+            2f64.powi(-60)
+        } else {
+            0.0
+        };
+        assert_eq!(dot_product(a, b)?, expected);
+
+        let a = Vector::new(vec![1, 2, 3]);
+        let b = Vector::new(vec![4, 5, 6]);
+        assert_eq!(dot_product(a, b)?, 32);
+
+        // integers wrap rather than panic, in debug builds too
+        let a = Vector::new(vec![i32::MAX, 1]);
+        let b = Vector::new(vec![2, 2]);
+        assert_eq!(dot_product(a, b)?, 0);
+
+        let a = Vector::new(vec![Wrapping(i32::MAX), Wrapping(1)]);
+        let b = Vector::new(vec![Wrapping(2), Wrapping(2)]);
+        assert_eq!(dot_product(a, b)?, Wrapping(0));
+        Ok(())
+    }
+}